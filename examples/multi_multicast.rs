@@ -1,9 +1,8 @@
 use std::net::{Ipv4Addr, SocketAddrV4};
-use tokio::sync::broadcast::Receiver;
 use clap::Parser;
-use rudi::{udpmanager::UdpManager, CastMode, Datagram, IpConfigV4, MulticastConfig};
+use rudi::{subscription::Subscription, udpmanager::UdpManager, CastMode, Interface, IpConfigV4, MulticastConfig, SourceFilter};
 
-async fn recv_data(rx: &mut Receiver<Datagram>, name: &str) {
+async fn recv_data(rx: &mut Subscription, name: &str) {
     while let Ok(data) = rx.recv().await {
         println!("received {} bytes of data from {name}", data.payload.len());
     }
@@ -16,13 +15,25 @@ struct Args {
     #[arg(short, long, default_value_t = String::from("224.1.1.100"))]
     group: String,
 
-    /// Interface to join multicast group on
+    /// Interface to join multicast group on, by address or by name
     #[arg(short, long, default_value_t = String::from("0.0.0.0"))]
     interface: String,
 
+    /// Only receive from this source (source-specific multicast), can be repeated
+    #[arg(short, long)]
+    source: Vec<String>,
+
     /// Interface and Port to bind socket to
     #[arg(short, long, default_value_t = String::from("0.0.0.0:6993"))]
     destination: String,
+
+    /// Network device to bind socket to, e.g. eth1
+    #[arg(long)]
+    device: Option<String>,
+
+    /// Whole config in one string, e.g. udp+mcast://224.1.1.100@eth0:6993, instead of the options above
+    #[arg(long)]
+    config: Option<IpConfigV4>,
 }
 
 #[tokio::main]
@@ -30,18 +41,29 @@ async fn main() {
     let args = Args::parse();
 
     let mut udp = UdpManager::default();
-    let mcast = IpConfigV4 {
+    let mcast = args.config.clone().unwrap_or_else(|| IpConfigV4 {
         cast_mode: CastMode::Multicast(MulticastConfig{
             group: args.group.parse::<Ipv4Addr>().unwrap(),
-            interface: args.interface.parse::<Ipv4Addr>().unwrap(),
+            interface: match args.interface.parse::<Ipv4Addr>() {
+                Ok(addr) => Interface::Addr(addr),
+                Err(_) => Interface::Name(args.interface.clone()),
+            },
+            sources: if args.source.is_empty() {
+                SourceFilter::Any
+            } else {
+                SourceFilter::Include(args.source.iter().map(|s| s.parse::<Ipv4Addr>().unwrap()).collect())
+            },
         }),
         bind_addr: args.destination.parse::<SocketAddrV4>().unwrap(),
-    };
+        device: args.device.clone(),
+        sharing: None,
+    });
 
     let mut rx1 = udp.subscribe(&(mcast.clone()),None).await.unwrap();
     let mut rx2 = udp.subscribe(&(mcast.clone()),None).await.unwrap();
 
     println!("{:?}",args);
+    println!("receiving on {}", mcast);
 
     let mut ctrlc = tokio::spawn(tokio::signal::ctrl_c());
 
@@ -54,4 +76,6 @@ async fn main() {
     }
 
     println!("ctrlc called. exiting...");
+
+    udp.shutdown().await;
 }
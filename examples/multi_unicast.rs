@@ -1,9 +1,8 @@
 use std::net::SocketAddrV4;
-use tokio::sync::broadcast::Receiver;
 use clap::Parser;
-use rudi::{udpmanager::UdpManager, CastMode, Datagram, IpConfigV4};
+use rudi::{subscription::Subscription, udpmanager::UdpManager, CastMode, IpConfigV4};
 
-async fn recv_data(rx: &mut Receiver<Datagram>, name: &str) {
+async fn recv_data(rx: &mut Subscription, name: &str) {
     while let Ok(data) = rx.recv().await {
         println!("received {} bytes of data from {name}", data.payload.len());
     }
@@ -19,6 +18,14 @@ struct Args {
     /// Interface and Port to bind socket to
     #[arg(short, long, default_value_t = String::from("0.0.0.0:6993"))]
     destination: String,
+
+    /// Network device to bind socket to, e.g. eth1
+    #[arg(long)]
+    device: Option<String>,
+
+    /// Whole config in one string, e.g. udp://0.0.0.0:6993?peer=127.0.0.1:7000, instead of the options above
+    #[arg(long)]
+    config: Option<IpConfigV4>,
 }
 
 #[tokio::main]
@@ -28,15 +35,18 @@ async fn main() {
 
     let mut udp = UdpManager::default();
     
-    let unicast = IpConfigV4 {
+    let unicast = args.config.clone().unwrap_or_else(|| IpConfigV4 {
         cast_mode: CastMode::Unicast(args.source.parse::<SocketAddrV4>().unwrap()),
         bind_addr: args.destination.parse::<SocketAddrV4>().unwrap(),
-    };
+        device: args.device.clone(),
+        sharing: None,
+    });
 
     let mut rx1 = udp.subscribe(&(unicast.clone()),None).await.unwrap();
     let mut rx2 = udp.subscribe(&(unicast.clone()),None).await.unwrap();
 
     println!("{:?}",args);
+    println!("receiving on {}", unicast);
 
     let mut ctrlc = tokio::spawn(tokio::signal::ctrl_c());
 
@@ -49,4 +59,6 @@ async fn main() {
     }
 
     println!("ctrlc called. exiting...");
+
+    udp.shutdown().await;
 }
@@ -0,0 +1,140 @@
+//! which configs can be open next to each other. the kernel hands a unicast datagram to only one
+//! of the sockets bound to an address, so configs that would split traffic that way are refused
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{IpConfig, SocketSharing};
+
+#[derive(PartialEq,Eq,Clone,Copy,Debug)]
+pub enum ConflictReason {
+    /// one of them is unicast and its datagrams would only reach one of the sockets
+    Unicast,
+    /// one of them doesn't allow sharing its address or they share it in different ways
+    Sharing,
+    /// they would share a socket but are for the same unicast peer, or both any-source
+    SamePeer,
+    /// they would share a socket but ask for different socket level `ConnectionOptions`
+    Options,
+}
+
+/// returned inside an `io::Error` of kind `AddrInUse` when a config overlaps one that is already open,
+/// get it back with `err.get_ref().and_then(|e| e.downcast_ref::<Conflict>())`
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub struct Conflict {
+    pub config: IpConfig,
+    pub existing: IpConfig,
+    pub reason: ConflictReason,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} overlaps already open {:?}: ", self.config, self.existing)?;
+        match self.reason {
+            ConflictReason::Unicast => write!(f, "unicast datagrams would only reach one of them"),
+            ConflictReason::Sharing => write!(f, "their sockets can't share the address"),
+            ConflictReason::SamePeer => write!(f, "both would receive the same senders on one socket"),
+            ConflictReason::Options => write!(f, "they would share a socket but ask for different options"),
+        }
+    }
+}
+
+impl Error for Conflict {}
+
+fn is_unicast(config: &IpConfig) -> bool {
+    config.unicast_peer().is_some() || config.is_any_source()
+}
+
+//whether both sockets would get datagrams sent to the same address and port
+fn overlaps(a: &IpConfig, b: &IpConfig) -> bool {
+    let (a_addr, b_addr) = (a.bind_addr(), b.bind_addr());
+    let devices = match (a.device(), b.device()) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    };
+    a_addr.port() == b_addr.port()
+        && a_addr.is_ipv4() == b_addr.is_ipv4()
+        && (a_addr.ip() == b_addr.ip() || a_addr.ip().is_unspecified() || b_addr.ip().is_unspecified())
+        && devices
+}
+
+//only set sharing counts for unicast, the default for it is exclusive
+fn explicitly_shared(config: &IpConfig) -> bool {
+    config.configured_sharing().is_some()
+}
+
+/// why `config` can't be opened next to `existing`, if it can't. the same config and unicast configs that
+/// share a socket are fine, other overlapping configs have to share the address the same way and unicast
+/// ones have to have asked for it
+pub(crate) fn check(config: &IpConfig, existing: &IpConfig) -> Option<ConflictReason> {
+    if config == existing || config.shares_socket(existing) || !overlaps(config, existing) {
+        return None;
+    }
+    if [config, existing].into_iter().any(|config| is_unicast(config) && !explicitly_shared(config)) {
+        return Some(ConflictReason::Unicast);
+    }
+    if config.sharing() != existing.sharing() || config.sharing() == SocketSharing::Exclusive {
+        return Some(ConflictReason::Sharing);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    use super::{check, ConflictReason};
+    use crate::{CastMode, Interface, IpConfig, IpConfigV4, MulticastConfig, SocketSharing, SourceFilter};
+
+    fn config(cast_mode: CastMode, bind_addr: &str) -> IpConfig {
+        IpConfigV4 {
+            cast_mode,
+            bind_addr: bind_addr.parse::<SocketAddrV4>().unwrap(),
+            device: None,
+            sharing: None,
+        }
+        .into()
+    }
+
+    fn multicast(group: &str) -> CastMode {
+        CastMode::Multicast(MulticastConfig {
+            group: group.parse::<Ipv4Addr>().unwrap(),
+            interface: Interface::Addr(Ipv4Addr::UNSPECIFIED),
+            sources: SourceFilter::Any,
+        })
+    }
+
+    fn shared(mut config: IpConfig, sharing: SocketSharing) -> IpConfig {
+        if let IpConfig::V4(config) = &mut config {
+            config.sharing = Some(sharing);
+        }
+        config
+    }
+
+    fn reason(config: &IpConfig, existing: &IpConfig) -> ConflictReason {
+        check(config, existing).unwrap()
+    }
+
+    #[test]
+    fn it_allows() {
+        let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse().unwrap()), "0.0.0.0:6993");
+        let mcast = config(multicast("225.1.1.1"), "0.0.0.0:6993");
+        assert!(check(&unicast, &unicast).is_none());
+        assert!(check(&unicast, &config(CastMode::AnySource, "0.0.0.0:6993")).is_none());
+        assert!(check(&unicast, &config(CastMode::AnySource, "0.0.0.0:6994")).is_none());
+        assert!(check(&mcast, &config(CastMode::Broadcast, "127.0.0.1:6993")).is_none());
+        assert!(check(&shared(unicast, SocketSharing::ReuseAddress), &mcast).is_none());
+    }
+
+    #[test]
+    fn it_refuses() {
+        let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse().unwrap()), "0.0.0.0:6993");
+        let mcast = config(multicast("225.1.1.1"), "0.0.0.0:6993");
+        assert_eq!(reason(&config(CastMode::AnySource, "127.0.0.1:6993"), &unicast), ConflictReason::Unicast);
+        assert_eq!(reason(&mcast, &unicast), ConflictReason::Unicast);
+        let broadcast = config(CastMode::Broadcast, "127.0.0.1:6993");
+        assert_eq!(reason(&shared(broadcast, SocketSharing::ReusePort), &mcast), ConflictReason::Sharing);
+        let any = config(CastMode::AnySource, "0.0.0.0:6993");
+        assert_eq!(reason(&shared(any, SocketSharing::ReusePort), &mcast), ConflictReason::Sharing);
+    }
+}
@@ -1,113 +1,910 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
 
-use crate::{Datagram, IpConfigV4};
-use tokio::sync::broadcast::{Receiver, Sender};
-use socket2::{Domain, Protocol, SockAddr, Socket, Type};
-use tokio::io;
+use crate::conflict::{Conflict, ConflictReason};
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+use crate::options::{ConnectionOptions, Distribution, SocketOptions};
+use crate::pool::BufferPool;
+use crate::stats::{ConnectionStats, Counters};
+use crate::sys::{RecvBatch, RecvMeta};
+use crate::subscription::Subscription;
+use crate::{sys, CastMode, CastModeV6, ConnectionEvent, Datagram, Interface, IpConfig, IpConfigV4, IpConfigV6, SocketSharing, SourceFilter, Timestamp};
+use bytes::Bytes;
+use tokio::sync::broadcast::Sender;
+use crate::distribution::Distributor;
+use tokio::sync::Notify;
+use socket2::{Domain, InterfaceIndexOrAddress, Protocol, SockAddr, SockRef, Socket, Type};
+use tokio::io::{self, Interest};
 use tokio::net::UdpSocket;
 
 const MAX_DATAGRAM_SIZE: usize = 65507;
+//v6 headers aren't counted against the payload length, only the udp header is
+const MAX_DATAGRAM_SIZE_V6: usize = 65527;
+//a GRO read can hold a whole burst of datagrams, up to the size of a single ip packet
+const MAX_GRO_SIZE: usize = u16::MAX as usize;
+//datagrams a receive buffer chunk holds before another one is needed
+const POOL_SEGMENTS: usize = 16;
 
-fn make_udp_socket(addr: &SockAddr, _reuse_port: bool) -> io::Result<Socket> {
-    let sock = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
-    sock.set_reuse_address(true)?;
-    sock.set_nonblocking(true)?;
-    sock.bind(addr)?;
+//subscriber count of a connection that has been torn down, it can never be subscribed to again
+const CLOSED: usize = usize::MAX;
+
+fn make_udp_socket(ip_config: &IpConfig, options: &ConnectionOptions) -> Result<Socket> {
+    let addr = ip_config.bind_addr();
+    let io = |source| Error::Io { config: ip_config.clone(), source };
+    let sock = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP)).map_err(io)?;
+    if addr.is_ipv6() {
+        //keep v6 sockets from also picking up v4 traffic that belongs to an IpConfigV4 on the same port
+        sock.set_only_v6(true).map_err(io)?;
+    }
+    match ip_config.sharing() {
+        SocketSharing::Exclusive => {}
+        SocketSharing::ReuseAddress => sock.set_reuse_address(true).map_err(io)?,
+        SocketSharing::ReusePort => sock.set_reuse_port(true).map_err(io)?,
+    }
+    sock.set_nonblocking(true).map_err(io)?;
+    if addr.is_ipv6() && options.socket.multicast_interface.is_some() {
+        return Err(Error::InvalidConfig {
+            config: ip_config.clone(),
+            source: io::Error::new(io::ErrorKind::InvalidInput, "multicast_interface only applies to v4 sockets"),
+        });
+    }
+    set_socket_options(&sock, &options.socket, addr.is_ipv6()).map_err(io)?;
+    sys::enable_pktinfo(&sock, addr.is_ipv6()).map_err(io)?;
+    if options.timestamps {
+        sys::enable_timestamps(&sock).map_err(io)?;
+    }
+    if options.gro {
+        sys::enable_gro(&sock).map_err(io)?;
+    }
+    if let Some(device) = ip_config.device() {
+        sock.bind_device(Some(device.as_bytes())).map_err(|source| Error::Interface { config: ip_config.clone(), source })?;
+    }
+    sock.bind(&SockAddr::from(addr)).map_err(|source| Error::socket(ip_config, source))?;
     Ok(sock)
 }
 
-pub struct Connection {
-    tx: Sender<Datagram>,
-    pub socket: Arc<UdpSocket>,
+fn set_socket_options(sock: &Socket, options: &SocketOptions, ipv6: bool) -> io::Result<()> {
+    if let Some(size) = options.recv_buffer {
+        if options.force_recv_buffer {
+            sys::set_recv_buffer_force(sock, size)?;
+        } else {
+            sock.set_recv_buffer_size(size)?;
+        }
+    }
+    if let Some(size) = options.send_buffer {
+        sock.set_send_buffer_size(size)?;
+    }
+    if let Some(tos) = options.tos {
+        if ipv6 { sock.set_tclass_v6(tos)? } else { sock.set_tos(tos)? }
+    }
+    if let Some(ttl) = options.ttl {
+        if ipv6 { sock.set_unicast_hops_v6(ttl)? } else { sock.set_ttl(ttl)? }
+    }
+    if let Some(ttl) = options.multicast_ttl {
+        if ipv6 { sock.set_multicast_hops_v6(ttl)? } else { sock.set_multicast_ttl_v4(ttl)? }
+    }
+    if let Some(on) = options.multicast_loop {
+        if ipv6 { sock.set_multicast_loop_v6(on)? } else { sock.set_multicast_loop_v4(on)? }
+    }
+    if let Some(interface) = options.multicast_interface {
+        sock.set_multicast_if_v4(&interface)?;
+    }
+    if let Some(priority) = options.priority {
+        sys::set_priority(sock, priority)?;
+    }
+    if let Some(mark) = options.mark {
+        sock.set_mark(mark)?;
+    }
+    Ok(())
 }
 
-impl Connection {
-    pub async fn new(ip_config: &IpConfigV4, tx: Sender<Datagram>) -> io::Result<Self> {
-        let s = make_udp_socket(
-            &SockAddr::from(ip_config.bind_addr),
-            //we dont want to allow port reuse for unicast, otherwise another listener on the same port could steal data
-            match ip_config.cast_mode {
-                crate::CastMode::Unicast(_) => false,
-                _ => true,
+//every option as the kernel reports it, so values it rounded or doubled show up as such
+fn socket_options(socket: &UdpSocket) -> io::Result<SocketOptions> {
+    let sock = SockRef::from(socket);
+    let ipv6 = socket.local_addr()?.is_ipv6();
+    Ok(SocketOptions {
+        recv_buffer: Some(sock.recv_buffer_size()?),
+        force_recv_buffer: false,
+        send_buffer: Some(sock.send_buffer_size()?),
+        tos: Some(if ipv6 { sock.tclass_v6()? } else { sock.tos()? }),
+        ttl: Some(if ipv6 { sock.unicast_hops_v6()? } else { sock.ttl()? }),
+        multicast_ttl: Some(if ipv6 { sock.multicast_hops_v6()? } else { sock.multicast_ttl_v4()? }),
+        multicast_loop: Some(if ipv6 { sock.multicast_loop_v6()? } else { sock.multicast_loop_v4()? }),
+        multicast_interface: if ipv6 { None } else { Some(sock.multicast_if_v4()?) },
+        priority: Some(sys::priority(socket)?),
+        mark: Some(sock.mark()?),
+    })
+}
+
+/// state shared between a connection, its recv task and its subscriptions
+pub(crate) struct Shared {
+    config: IpConfig,
+    //taken by the recv task when it lets go of the connection so every subscriber sees `Closed`
+    tx: Mutex<Option<Distributor>>,
+    //filtered subscribers each get a channel of their own, cleared along with `tx`
+    routes: Mutex<Vec<(Filter, Distributor)>>,
+    distribution: Distribution,
+    channel_size: usize,
+    subscribers: AtomicUsize,
+    //the recv task's, shared by every connection on the socket
+    wake: Arc<Notify>,
+    detached: Notify,
+    pub(crate) counters: Counters,
+}
+
+impl Shared {
+    fn new(config: IpConfig, options: &ConnectionOptions, tx: Distributor, wake: Arc<Notify>) -> Self {
+        Shared {
+            config,
+            tx: Mutex::new(Some(tx)),
+            routes: Mutex::new(Vec::new()),
+            distribution: options.distribution,
+            channel_size: options.channel_size.unwrap_or(u16::MAX as usize),
+            subscribers: AtomicUsize::new(0),
+            wake,
+            detached: Notify::new(),
+            counters: Counters::default(),
+        }
+    }
+
+    fn subscribe(self: &Arc<Self>, filter: Option<Filter>) -> Option<Subscription> {
+        self.subscribers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n != CLOSED).then(|| n + 1))
+            .ok()?;
+        //held while a route is added so the recv task can't clear the routes before it is in
+        let tx = self.tx.lock().unwrap();
+        let rx = tx.as_ref().map(|tx| match filter {
+            Some(filter) => {
+                let route = Distributor::new(self.distribution, self.channel_size);
+                let rx = route.subscribe();
+                self.routes.lock().unwrap().push((filter, route));
+                rx
+            }
+            None => tx.subscribe(),
+        });
+        drop(tx);
+        match rx {
+            Some(rx) => Some(Subscription::new(rx, self.clone())),
+            None => {
+                self.release();
+                None
+            }
+        }
+    }
+
+    pub(crate) fn release(&self) {
+        //the last subscriber closes the connection on its way out
+        let released = self.subscribers.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| match n {
+            CLOSED => None,
+            1 => Some(CLOSED),
+            n => Some(n - 1),
+        });
+        if released == Ok(1) {
+            self.wake.notify_one();
+        }
+    }
+
+    fn close(&self) {
+        self.subscribers.store(CLOSED, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.subscribers.load(Ordering::SeqCst) == CLOSED
+    }
+
+    fn has_routes(&self) -> bool {
+        !self.routes.lock().unwrap().is_empty()
+    }
+
+    //the channels of the filtered subscribers `datagram` is for, forgetting the ones nobody listens on anymore
+    fn routes(&self, datagram: &Datagram) -> Vec<Distributor> {
+        let mut routes = self.routes.lock().unwrap();
+        routes.retain(|(_, route)| route.receiver_count() > 0);
+        routes
+            .iter()
+            .filter(|(filter, _)| filter.matches(datagram))
+            .map(|(_, route)| route.clone())
+            .collect()
+    }
+
+    async fn closed(&self) {
+        while !self.is_closed() {
+            self.wake.notified().await;
+        }
+    }
+
+    //called by the recv task once it no longer hands this connection anything
+    fn detach(&self) {
+        let tx = self.tx.lock().unwrap().take();
+        self.routes.lock().unwrap().clear();
+        drop(tx);
+        self.detached.notify_one();
+    }
+
+    fn subscriber_count(&self) -> usize {
+        match self.subscribers.load(Ordering::SeqCst) {
+            CLOSED => 0,
+            n => n,
+        }
+    }
+}
+
+//errors like an icmp port unreachable only flag the socket as errored, not as readable,
+//so they have to be picked up separately or they would sit there until the next datagram arrives
+async fn pending_error(socket: &UdpSocket) -> io::Error {
+    let taken = socket
+        .async_io(Interest::ERROR, || match socket.take_error() {
+            Ok(Some(e)) => Ok(e),
+            Ok(None) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(e) => Ok(e),
+        })
+        .await;
+    match taken {
+        Ok(e) | Err(e) => e,
+    }
+}
+
+//reads datagrams one `recvmsg` at a time, or in batches with `recvmmsg`
+enum Reader {
+    Single(BufferPool),
+    Batch(BufferPool, RecvBatch),
+}
+
+impl Reader {
+    fn new(options: &ConnectionOptions, ipv6: bool) -> Self {
+        let segment = match (options.gro, ipv6) {
+            (true, _) => MAX_GRO_SIZE,
+            (false, false) => MAX_DATAGRAM_SIZE,
+            (false, true) => MAX_DATAGRAM_SIZE_V6,
+        };
+        let pool = BufferPool::new(segment, POOL_SEGMENTS);
+        match options.batch_size {
+            Some(count) if count > 1 => Reader::Batch(pool, RecvBatch::new(segment, count)),
+            _ => Reader::Single(pool),
+        }
+    }
+
+    //one non-blocking read
+    fn read(&mut self, socket: &UdpSocket, out: &mut Vec<(RecvMeta, Bytes)>) -> io::Result<()> {
+        match self {
+            Reader::Single(pool) => {
+                let mut meta = sys::recv_msg(socket, pool.spare())?;
+                meta.received = Some(SystemTime::now());
+                //recv_msg has written the first `len` bytes
+                let payload = unsafe { pool.filled(meta.len) };
+                push_segments(out, meta, payload);
+            }
+            Reader::Batch(pool, batch) => {
+                let count = batch.recv(socket)?;
+                let received = SystemTime::now();
+                //the batch buffers are reused for the next read, so payloads are copied out into
+                //the pool, which only holds on to as much as each datagram needs
+                for i in 0..count {
+                    let (meta, data) = batch.datagram(i);
+                    push_segments(out, RecvMeta { received: Some(received), ..meta.clone() }, pool.copy_from(data));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+//splits a coalesced GRO read back into its datagrams, they all share the one buffer
+fn push_segments(out: &mut Vec<(RecvMeta, Bytes)>, meta: RecvMeta, mut payload: Bytes) {
+    let Some(size) = meta.segment_size.filter(|&size| size < payload.len()) else {
+        out.push((meta, payload));
+        return;
+    };
+    while !payload.is_empty() {
+        let segment = payload.split_to(size.min(payload.len()));
+        out.push((RecvMeta { len: segment.len(), segment_size: None, ..meta.clone() }, segment));
+    }
+}
+
+async fn recv_datagrams(socket: &UdpSocket, reader: &mut Reader, out: &mut Vec<(RecvMeta, Bytes)>) -> io::Result<()> {
+    let received = socket.async_io(Interest::READABLE, || reader.read(socket, out));
+    tokio::select! {
+        received = received => received,
+        e = pending_error(socket) => Err(e),
+    }
+}
+
+//an address to join on for the calls that only take one, source-specific joins and blocks
+fn interface_addr(interface: &Interface) -> io::Result<Ipv4Addr> {
+    match interface {
+        Interface::Addr(addr) => Ok(*addr),
+        Interface::Name(name) => sys::interface_addr_v4(name),
+    }
+}
+
+//the interface a v4 group was joined on, as it was looked up then. leaving has to go by the same one
+//even if a named interface has changed its address since
+#[derive(Clone,Copy)]
+enum JoinedOn {
+    //any-source joins by name go by interface index (ip_mreqn)
+    Index(u32),
+    Addr(Ipv4Addr),
+}
+
+impl From<JoinedOn> for InterfaceIndexOrAddress {
+    fn from(joined: JoinedOn) -> Self {
+        match joined {
+            JoinedOn::Index(index) => InterfaceIndexOrAddress::Index(index),
+            JoinedOn::Addr(addr) => InterfaceIndexOrAddress::Address(addr),
+        }
+    }
+}
+
+fn interface_index(interface: &Interface) -> io::Result<JoinedOn> {
+    match interface {
+        Interface::Addr(addr) => Ok(JoinedOn::Addr(*addr)),
+        Interface::Name(name) => sys::interface_index(name).map(JoinedOn::Index),
+    }
+}
+
+fn join_multicast(socket: &UdpSocket, ip_config: &IpConfig) -> Result<Option<JoinedOn>> {
+    let no_interface = |source| Error::Interface { config: ip_config.clone(), source };
+    let join_failed = |source| Error::Join { config: ip_config.clone(), source };
+    match ip_config {
+        IpConfig::V4(config) => match &config.cast_mode {
+            CastMode::Multicast(mcast_config) => match &mcast_config.sources {
+                SourceFilter::Any => {
+                    let joined = interface_index(&mcast_config.interface).map_err(no_interface)?;
+                    SockRef::from(socket).join_multicast_v4_n(&mcast_config.group, &joined.into()).map_err(join_failed)?;
+                    Ok(Some(joined))
+                }
+                SourceFilter::Include(sources) => {
+                    if sources.is_empty() {
+                        return Err(Error::InvalidConfig {
+                            config: ip_config.clone(),
+                            source: io::Error::new(io::ErrorKind::InvalidInput, "source-specific multicast needs at least one source"),
+                        });
+                    }
+                    let interface = interface_addr(&mcast_config.interface).map_err(no_interface)?;
+                    for source in sources {
+                        SockRef::from(socket).join_ssm_v4(source, &mcast_config.group, &interface).map_err(join_failed)?;
+                    }
+                    Ok(Some(JoinedOn::Addr(interface)))
+                }
+                SourceFilter::Exclude(sources) => {
+                    let interface = interface_addr(&mcast_config.interface).map_err(no_interface)?;
+                    socket.join_multicast_v4(mcast_config.group, interface).map_err(join_failed)?;
+                    for source in sources {
+                        sys::block_source_v4(socket, mcast_config.group, interface, *source).map_err(join_failed)?;
+                    }
+                    Ok(Some(JoinedOn::Addr(interface)))
+                }
             },
-        )?;
+            _ => Ok(None),
+        },
+        IpConfig::V6(config) => match &config.cast_mode {
+            CastModeV6::Multicast(mcast_config) => {
+                socket.join_multicast_v6(&mcast_config.group, mcast_config.interface).map_err(join_failed)?;
+                Ok(None)
+            }
+            _ => Ok(None),
+        },
+    }
+}
 
-        let socket = UdpSocket::from_std(s.into())?;
+fn leave_multicast(socket: &UdpSocket, ip_config: &IpConfig, joined: Option<JoinedOn>) -> io::Result<()> {
+    match (ip_config, joined) {
+        (IpConfig::V4(IpConfigV4 { cast_mode: CastMode::Multicast(mcast_config), .. }), Some(joined)) => {
+            match (&mcast_config.sources, joined) {
+                (SourceFilter::Include(sources), JoinedOn::Addr(interface)) => {
+                    for source in sources {
+                        SockRef::from(socket).leave_ssm_v4(source, &mcast_config.group, &interface)?;
+                    }
+                    Ok(())
+                }
+                //dropping the membership also drops its blocked sources
+                (SourceFilter::Exclude(_), JoinedOn::Addr(interface)) => socket.leave_multicast_v4(mcast_config.group, interface),
+                (_, joined) => SockRef::from(socket).leave_multicast_v4_n(&mcast_config.group, &joined.into()),
+            }
+        }
+        (IpConfig::V6(IpConfigV6 { cast_mode: CastModeV6::Multicast(mcast_config), .. }), _) => {
+            socket.leave_multicast_v6(&mcast_config.group, mcast_config.interface)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// errors that can clear up on their own, e.g. a unicast peer that isn't listening yet.
+/// anything else means the socket is unusable and the connection is closed
+fn is_transient(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::OutOfMemory
+    )
+}
+
+const MIN_BACKOFF: Duration = Duration::from_millis(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+//doubles the delay after every consecutive transient error so a dead peer can't peg a core
+struct Backoff {
+    delay: Duration,
+}
 
-        match &ip_config.cast_mode {
-            crate::CastMode::Unicast(addr) => {
-                socket.connect(addr.to_string()).await?;
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff { delay: MIN_BACKOFF }
+    }
+}
+
+impl Backoff {
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (self.delay * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.delay = MIN_BACKOFF;
+    }
+}
+
+//a connection as seen by the recv task
+struct Peer {
+    shared: Arc<Shared>,
+    tx: Distributor,
+}
+
+//unicast peers are matched on address and port only, v6 senders also carry a flow label and scope id
+fn peer_key(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip(), addr.port())
+}
+
+//the connections sharing a socket. unicast configs on the same bind address get one socket
+//between them and datagrams are handed to the config of their sender
+#[derive(Default)]
+struct Demux {
+    peers: HashMap<SocketAddr, Arc<Peer>>,
+    //multicast, broadcast or any-source, on a unicast socket it only gets datagrams no known peer sent
+    any: Option<Arc<Peer>>,
+    //the socket stays connected as long as it serves a single unicast peer
+    connected: bool,
+    //set by the recv task on its way out, nothing can attach anymore
+    closed: bool,
+}
+
+impl Demux {
+    fn iter(&self) -> impl Iterator<Item = &Arc<Peer>> {
+        self.peers.values().chain(self.any.iter())
+    }
+
+    fn route(&self, sender: SocketAddr) -> Option<Arc<Peer>> {
+        self.peers.get(&peer_key(sender)).or(self.any.as_ref()).cloned()
+    }
+
+    //takes out the closed peers, `None` if every one of them is closed and the recv task is done
+    fn take_closed(&mut self) -> Option<Vec<Arc<Peer>>> {
+        if self.iter().all(|peer| peer.shared.is_closed()) {
+            return None;
+        }
+        let mut closed = Vec::new();
+        self.peers.retain(|_, peer| {
+            if peer.shared.is_closed() {
+                closed.push(peer.clone());
             }
-            crate::CastMode::Broadcast => {
-                socket.set_broadcast(true)?;
+            !peer.shared.is_closed()
+        });
+        if self.any.as_ref().is_some_and(|peer| peer.shared.is_closed()) {
+            closed.extend(self.any.take());
+        }
+        Some(closed)
+    }
+
+    //the peer to connect to while it is the only one left
+    fn single_peer(&self) -> Option<SocketAddr> {
+        match (self.peers.len(), &self.any) {
+            (1, None) => self.peers.values().next().and_then(|peer| peer.shared.config.unicast_peer()),
+            _ => None,
+        }
+    }
+}
+
+//what a connection keeps of the socket it shares with others
+struct Endpoint {
+    socket: Weak<UdpSocket>,
+    demux: Arc<Mutex<Demux>>,
+    wake: Arc<Notify>,
+    //how the socket was set up, configs that join it have to ask for the same
+    sharing: SocketSharing,
+    options: ConnectionOptions,
+}
+
+//the options that go with the socket rather than with a connection on it
+fn same_socket_options(a: &ConnectionOptions, b: &ConnectionOptions) -> bool {
+    a.socket == b.socket && a.timestamps == b.timestamps && a.batch_size == b.batch_size && a.gro == b.gro
+}
+
+struct RecvTask {
+    //the config the socket was opened for, whose multicast group it has joined
+    config: IpConfig,
+    socket: Arc<UdpSocket>,
+    demux: Arc<Mutex<Demux>>,
+    wake: Arc<Notify>,
+    events: Sender<ConnectionEvent>,
+    reader: Reader,
+    interface_names: HashMap<u32, Option<Arc<str>>>,
+    joined: Option<JoinedOn>,
+}
+
+impl RecvTask {
+    async fn run(mut self) {
+        let mut backoff = Backoff::default();
+        let mut received = Vec::new();
+        loop {
+            let result = tokio::select! {
+                _ = self.wake.notified() => {
+                    if self.detach_closed() {
+                        break;
+                    }
+                    continue;
+                }
+                result = recv_datagrams(&self.socket, &mut self.reader, &mut received) => result,
+            };
+            //a batch is handed out even if reading stopped early on an error
+            for (meta, payload) in received.drain(..) {
+                let route = self.demux.lock().unwrap().route(meta.sender);
+                //nobody subscribed to the sender and there is no catch-all
+                let Some(peer) = route else { continue };
+                //a lossless channel can hold the task up here, it still has to notice being closed
+                tokio::select! {
+                    _ = peer.shared.closed() => {}
+                    _ = self.distribute(&peer, meta, payload) => {}
+                }
             }
-            crate::CastMode::Multicast(mcast_config) => {
-                socket.join_multicast_v4(mcast_config.group, mcast_config.interface)?;
+            //waiting out a lossless channel can have used up a wakeup
+            if self.detach_closed() {
+                break;
             }
-        };
-        let tx_clone = tx.clone();
-        let socket_rx = Arc::new(socket);
-        let socket_tx = socket_rx.clone();
-        let cast_mode = ip_config.cast_mode.clone();
-        tokio::spawn(async move {
-            let mut buf = [0u8;MAX_DATAGRAM_SIZE];
-            loop {
-                match cast_mode {
-                    crate::CastMode::Unicast(addr) => {
-                        match socket_rx.recv(&mut buf).await {
-                            Ok(bytes_read) => {
-                                if tx.receiver_count() > 0 && bytes_read > 0 {
-                                    let data = if let Some(data) = buf.get(..bytes_read) {
-                                        data.into()
-                                    } else {
-                                        vec![]
-                                    };
-                                    if let Err(_) = tx.send(Datagram { payload: data.into(), sender: addr.into() }) {
-                                        //TODO: log error
-                                    };
-                                }
-                            }
-                            Err(_) => {
-                                //TODO: log error
-                            }
+            match result {
+                Ok(()) => backoff.reset(),
+                Err(e) => {
+                    let peers: Vec<Arc<Peer>> = self.demux.lock().unwrap().iter().cloned().collect();
+                    for peer in &peers {
+                        let config = &peer.shared.config;
+                        peer.shared.counters.recv_error();
+                        tracing::warn!(config = ?config, error = %e, "failed to receive datagram");
+                        let _ = self.events.send(ConnectionEvent::RecvError { config: config.clone(), kind: e.kind() });
+                    }
+                    if !is_transient(e.kind()) {
+                        for peer in &peers {
+                            let config = &peer.shared.config;
+                            tracing::error!(config = ?config, error = %e, "closing connection after fatal receive error");
+                            let _ = self.events.send(ConnectionEvent::Failed { config: config.clone(), kind: e.kind() });
+                            peer.shared.close();
                         }
-                    },
-                    crate::CastMode::Multicast(_) | crate::CastMode::Broadcast=> {
-                        match socket_rx.recv_from(&mut buf).await {
-                            Ok((bytes_read, sender)) => {
-                                if tx.receiver_count() > 0 && bytes_read > 0 {
-                                    let data = if let Some(data) = buf.get(..bytes_read) {
-                                        data.into()
-                                    } else {
-                                        vec![]
-                                    };
-                                    if let Err(_) = tx.send(Datagram { payload: data.into(), sender: sender }) {
-                                        //TODO: log error
-                                    };
-                                }
-                            }
-                            Err(_) => {
-                                //TODO: log error
+                        break;
+                    }
+                    tokio::select! {
+                        _ = self.wake.notified() => {
+                            if self.detach_closed() {
+                                break;
                             }
                         }
-                    },
+                        _ = tokio::time::sleep(backoff.next_delay()) => {}
+                    }
                 }
             }
+        }
+        let peers: Vec<Arc<Peer>> = {
+            let mut demux = self.demux.lock().unwrap();
+            demux.closed = true;
+            let peers = demux.iter().cloned().collect();
+            demux.peers.clear();
+            demux.any = None;
+            peers
+        };
+        if let Err(e) = leave_multicast(&self.socket, &self.config, self.joined) {
+            tracing::warn!(config = ?self.config, error = %e, "failed to leave multicast group");
+            let _ = self.events.send(ConnectionEvent::LeaveError { config: self.config.clone(), kind: e.kind() });
+        }
+        //the socket is closed before anyone waiting on `Connection::close` hears about it
+        drop(self.socket);
+        for peer in peers {
+            peer.shared.detach();
+        }
+    }
+
+    fn detach_closed(&self) -> bool {
+        let mut demux = self.demux.lock().unwrap();
+        let Some(closed) = demux.take_closed() else {
+            return true;
+        };
+        //down to a single peer again, before the others hear they are gone
+        if let Some(addr) = demux.single_peer().filter(|_| !demux.connected) {
+            match SockRef::from(&*self.socket).connect(&SockAddr::from(addr)) {
+                Ok(()) => demux.connected = true,
+                Err(e) => tracing::warn!(config = ?self.config, error = %e, "failed to reconnect to the remaining peer"),
+            }
+        }
+        for peer in closed {
+            peer.shared.detach();
+        }
+        false
+    }
+
+    async fn distribute(&mut self, peer: &Peer, meta: RecvMeta, payload: Bytes) {
+        let shared = &peer.shared;
+        let timestamp = match meta.timestamp {
+            Some(time) => Timestamp::Kernel(time),
+            None => Timestamp::Userspace(meta.received.unwrap_or_else(SystemTime::now)),
+        };
+        //a cut off datagram is not handed out as if it were whole
+        if meta.truncated {
+            shared.counters.recv_error();
+            tracing::warn!(config = ?shared.config, sender = %meta.sender, "dropping truncated datagram");
+            let _ = self.events.send(ConnectionEvent::RecvError { config: shared.config.clone(), kind: io::ErrorKind::InvalidData });
+            return;
+        }
+        shared.counters.datagram(meta.len);
+        if peer.tx.receiver_count() == 0 && !shared.has_routes() {
+            shared.counters.dropped();
+            return;
+        }
+        if meta.len == 0 {
+            return;
+        }
+        let interface_name = meta.interface_index.and_then(|index| {
+            self.interface_names
+                .entry(index)
+                .or_insert_with(|| sys::interface_name(index).map(Arc::from))
+                .clone()
         });
+        let datagram = Datagram {
+            payload,
+            sender: meta.sender,
+            destination: meta.destination,
+            interface_index: meta.interface_index,
+            interface_name,
+            timestamp,
+        };
+        for route in shared.routes(&datagram) {
+            //a filtered subscriber leaving in between is no different from one that never matched
+            let _ = route.send(datagram.clone()).await;
+        }
+        if peer.tx.receiver_count() == 0 {
+            return;
+        }
+        if peer.tx.send(datagram).await.is_err() {
+            shared.counters.dropped();
+            tracing::debug!(config = ?shared.config, "no subscriber left to distribute datagram to");
+            let _ = self.events.send(ConnectionEvent::DistributeError { config: shared.config.clone() });
+        }
+    }
+}
+
+pub struct Connection {
+    shared: Arc<Shared>,
+    endpoint: Endpoint,
+}
+
+impl Connection {
+    pub async fn new(
+        ip_config: &IpConfig,
+        options: &ConnectionOptions,
+        events: Sender<ConnectionEvent>,
+    ) -> Result<Self> {
+        let peer = ip_config.unicast_peer();
+        let io = |source| Error::Io { config: ip_config.clone(), source };
+
+        let s = make_udp_socket(ip_config, options)?;
+
+        let socket = UdpSocket::from_std(s.into()).map_err(io)?;
+
+        //while it is the only peer the kernel can filter for it, and report it being unreachable
+        if let Some(addr) = peer {
+            socket.connect(addr).await.map_err(io)?;
+        }
+        if let IpConfig::V4(IpConfigV4 { cast_mode: CastMode::Broadcast, .. }) = ip_config {
+            socket.set_broadcast(true).map_err(io)?;
+        }
+        //overlapping group configs each get their own groups only
+        match ip_config {
+            IpConfig::V4(IpConfigV4 { cast_mode: CastMode::Broadcast | CastMode::Multicast(_), .. }) => {
+                sys::disable_multicast_all(&socket, false).map_err(io)?
+            }
+            IpConfig::V6(IpConfigV6 { cast_mode: CastModeV6::Multicast(_), .. }) => sys::disable_multicast_all(&socket, true).map_err(io)?,
+            _ => {}
+        }
+        let joined = join_multicast(&socket, ip_config)?;
+        let wake = Arc::new(Notify::new());
+        let tx = Distributor::new(options.distribution, options.channel_size.unwrap_or(u16::MAX as usize));
+        let shared = Arc::new(Shared::new(ip_config.clone(), options, tx.clone(), wake.clone()));
+        let mut demux = Demux { connected: peer.is_some(), ..Default::default() };
+        let first = Arc::new(Peer { shared: shared.clone(), tx });
+        match peer {
+            Some(addr) => {
+                demux.peers.insert(peer_key(addr), first);
+            }
+            None => demux.any = Some(first),
+        }
+        let demux = Arc::new(Mutex::new(demux));
+        let socket_rx = Arc::new(socket);
+        let socket_tx = Arc::downgrade(&socket_rx);
+        let task = RecvTask {
+            config: ip_config.clone(),
+            socket: socket_rx,
+            demux: demux.clone(),
+            wake: wake.clone(),
+            events,
+            reader: Reader::new(options, ip_config.bind_addr().is_ipv6()),
+            interface_names: HashMap::new(),
+            joined,
+        };
+        tokio::spawn(task.run());
         Ok(Connection {
-            tx: tx_clone,
-            socket: socket_tx,
+            shared,
+            endpoint: Endpoint { socket: socket_tx, demux, wake, sharing: ip_config.sharing(), options: options.clone() },
         })
     }
 
-    pub fn subscribe(&self) -> Receiver<Datagram> {
-        self.tx.subscribe()
+    /// a connection for another unicast or any-source config on this connection's socket.
+    /// `None` if the socket is on its way out. the config can't ask for a different sharing or socket
+    /// level options than the socket was opened with, only `distribution` and `channel_size` are its own
+    pub(crate) fn attach(&self, ip_config: &IpConfig, options: &ConnectionOptions) -> Result<Option<Connection>> {
+        let mut demux = self.endpoint.demux.lock().unwrap();
+        let Some(socket) = self.endpoint.socket.upgrade().filter(|_| !demux.closed) else {
+            return Ok(None);
+        };
+        //configs that only differ in how they share the address would both be for the same senders
+        let same_peer = match ip_config.unicast_peer() {
+            Some(addr) => demux.peers.get(&peer_key(addr)),
+            None => demux.any.as_ref(),
+        };
+        let same_peer = same_peer.filter(|existing| !existing.shared.is_closed());
+        let reason = if same_peer.is_some() {
+            Some(ConflictReason::SamePeer)
+        } else if ip_config.configured_sharing().is_some_and(|sharing| sharing != self.endpoint.sharing) {
+            Some(ConflictReason::Sharing)
+        } else if !same_socket_options(options, &self.endpoint.options) {
+            Some(ConflictReason::Options)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            let existing = same_peer.map_or(&self.shared.config, |existing| &existing.shared.config);
+            let conflict = Conflict { config: ip_config.clone(), existing: existing.clone(), reason };
+            return Err(Error::AddrInUse { config: ip_config.clone(), source: io::Error::new(io::ErrorKind::AddrInUse, conflict) });
+        }
+        if demux.connected {
+            sys::disconnect(&*socket).map_err(|source| Error::Io { config: ip_config.clone(), source })?;
+            demux.connected = false;
+        }
+        let tx = Distributor::new(options.distribution, options.channel_size.unwrap_or(u16::MAX as usize));
+        let shared = Arc::new(Shared::new(ip_config.clone(), options, tx.clone(), self.endpoint.wake.clone()));
+        let peer = Arc::new(Peer { shared: shared.clone(), tx });
+        //a closed connection for the same config the recv task hasn't got round to yet
+        let replaced = match ip_config.unicast_peer() {
+            Some(addr) => demux.peers.insert(peer_key(addr), peer),
+            None => demux.any.replace(peer),
+        };
+        if let Some(replaced) = replaced {
+            replaced.shared.detach();
+        }
+        Ok(Some(Connection {
+            shared,
+            endpoint: Endpoint {
+                socket: self.endpoint.socket.clone(),
+                demux: self.endpoint.demux.clone(),
+                wake: self.endpoint.wake.clone(),
+                sharing: self.endpoint.sharing,
+                options: self.endpoint.options.clone(),
+            },
+        }))
+    }
+
+    /// returns `None` once the connection has been torn down
+    pub fn subscribe(&self) -> Option<Subscription> {
+        self.shared.subscribe(None)
+    }
+
+    /// a subscription that only receives the datagrams matching `filter`
+    pub fn subscribe_filtered(&self, filter: Filter) -> Option<Subscription> {
+        self.shared.subscribe(Some(filter))
+    }
+
+    pub fn socket(&self) -> Option<Arc<UdpSocket>> {
+        if self.is_closed() {
+            return None;
+        }
+        self.endpoint.socket.upgrade()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.is_closed()
+    }
+
+    /// the effective socket options, `None` once the connection is closed
+    pub fn socket_options(&self) -> Option<io::Result<SocketOptions>> {
+        self.socket().map(|socket| socket_options(&socket))
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        self.shared.counters.snapshot(self.shared.subscriber_count())
+    }
+
+    /// waits for the recv task to let go of the connection. if it was the last one on its socket
+    /// that is after leaving any multicast group and closing the socket
+    pub async fn close(self) {
+        self.shared.close();
+        let attached = self.shared.tx.lock().unwrap().is_some();
+        if attached {
+            self.shared.detached.notified().await;
+        }
+    }
+}
+
+//dropping a connection stops its recv task without waiting for it, use `close` to wait
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.shared.close();
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::len_zero)]
 mod tests {
-    use bytes::{BufMut, BytesMut};
+    use std::time::Duration;
+
+    use bytes::{BufMut, Bytes, BytesMut};
+    use tokio::io;
+
+    use super::{is_transient, push_segments, Backoff, RecvMeta, MAX_BACKOFF, MIN_BACKOFF};
+
+    #[test]
+    fn splits_coalesced_reads() {
+        let meta = RecvMeta {
+            len: 20,
+            sender: "127.0.0.1:6994".parse().unwrap(),
+            destination: None,
+            interface_index: None,
+            timestamp: None,
+            received: None,
+            segment_size: Some(8),
+            truncated: false,
+        };
+        let mut out = Vec::new();
+        push_segments(&mut out, meta, Bytes::from_static(b"deadbeefdeadbeefdead"));
+        let segments: Vec<_> = out.iter().map(|(meta, payload)| (meta.len, &payload[..])).collect();
+        assert_eq!(segments, [(8, &b"deadbeef"[..]), (8, &b"deadbeef"[..]), (4, &b"dead"[..])]);
+    }
+
+    #[test]
+    fn classifies_errors() {
+        assert!(is_transient(io::ErrorKind::ConnectionRefused));
+        assert!(is_transient(io::ErrorKind::Interrupted));
+        assert!(!is_transient(io::ErrorKind::NotConnected));
+        assert!(!is_transient(io::ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::default();
+        assert_eq!(backoff.next_delay(), MIN_BACKOFF);
+        assert_eq!(backoff.next_delay(), MIN_BACKOFF * 2);
+        for _ in 0..20 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.next_delay(), MAX_BACKOFF);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(1));
+    }
 
     #[test]
     fn it_works() {
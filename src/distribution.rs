@@ -0,0 +1,64 @@
+//! the channels a connection hands its datagrams to subscribers over, see `Distribution`
+
+use std::sync::Arc;
+
+use tokio::sync::{broadcast, watch};
+
+use crate::options::Distribution;
+use crate::Datagram;
+
+/// the sending half, the recv task and the connection each hold one
+#[derive(Clone)]
+pub(crate) enum Distributor {
+    Lossy(broadcast::Sender<Datagram>),
+    //the inactive receiver keeps the channel open while nobody is subscribed
+    Lossless(async_broadcast::Sender<Datagram>, async_broadcast::InactiveReceiver<Datagram>),
+    Latest(Arc<watch::Sender<Option<Datagram>>>),
+}
+
+/// the receiving half, one per subscription
+pub(crate) enum Receiver {
+    Lossy(broadcast::Receiver<Datagram>),
+    Lossless(async_broadcast::Receiver<Datagram>),
+    Latest(watch::Receiver<Option<Datagram>>),
+}
+
+impl Distributor {
+    pub(crate) fn new(distribution: Distribution, size: usize) -> Self {
+        match distribution {
+            Distribution::Lossy => Distributor::Lossy(broadcast::channel(size).0),
+            Distribution::Lossless => {
+                let (tx, rx) = async_broadcast::broadcast(size);
+                Distributor::Lossless(tx, rx.deactivate())
+            }
+            Distribution::Latest => Distributor::Latest(Arc::new(watch::channel(None).0)),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> Receiver {
+        match self {
+            Distributor::Lossy(tx) => Receiver::Lossy(tx.subscribe()),
+            Distributor::Lossless(_, rx) => Receiver::Lossless(rx.activate_cloned()),
+            Distributor::Latest(tx) => Receiver::Latest(tx.subscribe()),
+        }
+    }
+
+    pub(crate) fn receiver_count(&self) -> usize {
+        match self {
+            Distributor::Lossy(tx) => tx.receiver_count(),
+            Distributor::Lossless(tx, _) => tx.receiver_count(),
+            Distributor::Latest(tx) => tx.receiver_count(),
+        }
+    }
+
+    /// hands `datagram` to every subscriber. a lossless channel waits for the slowest one to make room,
+    /// which holds up reading the socket instead of dropping datagrams already read.
+    /// fails if nobody is subscribed anymore
+    pub(crate) async fn send(&self, datagram: Datagram) -> Result<(), Datagram> {
+        match self {
+            Distributor::Lossy(tx) => tx.send(datagram).map(|_| ()).map_err(|e| e.0),
+            Distributor::Lossless(tx, _) => tx.broadcast(datagram).await.map(|_| ()).map_err(|e| e.0),
+            Distributor::Latest(tx) => tx.send(Some(datagram)).map_err(|e| e.0.unwrap()),
+        }
+    }
+}
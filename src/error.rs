@@ -0,0 +1,104 @@
+//! the error returned by the public api, telling apart what a caller may want to retry from what
+//! needs a different config. every variant names the config it was about
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use tokio::io;
+
+use crate::conflict::Conflict;
+use crate::IpConfig;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// the config can't work as given, e.g. source-specific multicast without any sources
+    InvalidConfig { config: IpConfig, source: io::Error },
+    /// the address is taken, by another process or by an overlapping subscription of this manager,
+    /// in which case `source` wraps a `Conflict`
+    AddrInUse { config: IpConfig, source: io::Error },
+    /// the device or multicast interface named in the config doesn't exist or has no address
+    Interface { config: IpConfig, source: io::Error },
+    /// joining the multicast group failed
+    Join { config: IpConfig, source: io::Error },
+    /// `subscribe` was called after `UdpManager::shutdown`
+    ShutDown { config: IpConfig },
+    /// sending needs an open connection for the config
+    NotSubscribed { config: IpConfig },
+    /// anything else the socket reported, while opening it or sending on it
+    Io { config: IpConfig, source: io::Error },
+}
+
+impl Error {
+    //binding is where a taken address shows up
+    pub(crate) fn socket(config: &IpConfig, source: io::Error) -> Self {
+        match source.kind() {
+            io::ErrorKind::AddrInUse => Error::AddrInUse { config: config.clone(), source },
+            _ => Error::Io { config: config.clone(), source },
+        }
+    }
+
+    pub fn config(&self) -> &IpConfig {
+        match self {
+            Error::InvalidConfig { config, .. }
+            | Error::AddrInUse { config, .. }
+            | Error::Interface { config, .. }
+            | Error::Join { config, .. }
+            | Error::ShutDown { config }
+            | Error::NotSubscribed { config }
+            | Error::Io { config, .. } => config,
+        }
+    }
+
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Error::InvalidConfig { source, .. }
+            | Error::AddrInUse { source, .. }
+            | Error::Interface { source, .. }
+            | Error::Join { source, .. }
+            | Error::Io { source, .. } => Some(source),
+            Error::ShutDown { .. } | Error::NotSubscribed { .. } => None,
+        }
+    }
+
+    /// the kind of the underlying `io::Error`, `NotConnected` for the variants without one
+    pub fn kind(&self) -> io::ErrorKind {
+        self.io_error().map_or(io::ErrorKind::NotConnected, io::Error::kind)
+    }
+
+    /// the subscription this config overlaps, see `Conflict`
+    pub fn conflict(&self) -> Option<&Conflict> {
+        match self {
+            Error::AddrInUse { source, .. } => source.get_ref().and_then(|e| e.downcast_ref::<Conflict>()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidConfig { config, source } => write!(f, "invalid config {:?}: {}", config, source),
+            Error::AddrInUse { config, source } => write!(f, "address of {:?} is in use: {}", config, source),
+            Error::Interface { config, source } => write!(f, "interface of {:?} is unusable: {}", config, source),
+            Error::Join { config, source } => write!(f, "{:?} failed to join its group: {}", config, source),
+            Error::ShutDown { config } => write!(f, "can't open {:?}, udp manager has been shut down", config),
+            Error::NotSubscribed { config } => write!(f, "no open connection for {:?}", config),
+            Error::Io { config, source } => write!(f, "{:?}: {}", config, source),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.io_error().map(|e| e as _)
+    }
+}
+
+/// for callers that only deal in `io::Result`, the config stays in the message
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        io::Error::new(err.kind(), err)
+    }
+}
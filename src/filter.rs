@@ -0,0 +1,125 @@
+//! filters for `UdpManager::subscribe_filtered`, evaluated in the recv task so a subscriber's
+//! channel only ever holds the datagrams it asked for
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::Datagram;
+
+#[derive(Clone)]
+pub enum Filter {
+    Sender(SocketAddr),
+    SenderIp(IpAddr),
+    SenderPort(u16),
+    /// payloads starting with these bytes
+    Prefix(Bytes),
+    /// payloads where `payload[offset + i] & mask[i] == value[i] & mask[i]` for every byte of `mask`
+    Mask { offset: usize, mask: Bytes, value: Bytes },
+    /// payload length in bytes
+    Len(RangeInclusive<usize>),
+    Fn(Arc<dyn Fn(&Datagram) -> bool + Send + Sync>),
+    /// every filter has to match
+    All(Vec<Filter>),
+    /// at least one filter has to match
+    Any(Vec<Filter>),
+}
+
+impl Filter {
+    /// a filter running `f` on every datagram, keep it cheap as it holds up the recv task
+    pub fn func(f: impl Fn(&Datagram) -> bool + Send + Sync + 'static) -> Self {
+        Filter::Fn(Arc::new(f))
+    }
+
+    pub fn matches(&self, datagram: &Datagram) -> bool {
+        let payload = &datagram.payload;
+        match self {
+            Filter::Sender(addr) => datagram.sender == *addr,
+            Filter::SenderIp(ip) => datagram.sender.ip() == *ip,
+            Filter::SenderPort(port) => datagram.sender.port() == *port,
+            Filter::Prefix(prefix) => payload.starts_with(prefix),
+            Filter::Mask { offset, mask, value } => {
+                let Some(bytes) = payload.get(*offset..offset + mask.len()) else {
+                    return false;
+                };
+                bytes.iter().zip(mask.iter()).zip(value.iter()).all(|((b, m), v)| b & m == v & m)
+            }
+            Filter::Len(range) => range.contains(&payload.len()),
+            Filter::Fn(f) => f(datagram),
+            Filter::All(filters) => filters.iter().all(|filter| filter.matches(datagram)),
+            Filter::Any(filters) => filters.iter().any(|filter| filter.matches(datagram)),
+        }
+    }
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Sender(addr) => f.debug_tuple("Sender").field(addr).finish(),
+            Filter::SenderIp(ip) => f.debug_tuple("SenderIp").field(ip).finish(),
+            Filter::SenderPort(port) => f.debug_tuple("SenderPort").field(port).finish(),
+            Filter::Prefix(prefix) => f.debug_tuple("Prefix").field(prefix).finish(),
+            Filter::Mask { offset, mask, value } => {
+                f.debug_struct("Mask").field("offset", offset).field("mask", mask).field("value", value).finish()
+            }
+            Filter::Len(range) => f.debug_tuple("Len").field(range).finish(),
+            Filter::Fn(_) => f.write_str("Fn(..)"),
+            Filter::All(filters) => f.debug_tuple("All").field(filters).finish(),
+            Filter::Any(filters) => f.debug_tuple("Any").field(filters).finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use bytes::Bytes;
+
+    use super::Filter;
+    use crate::{Datagram, Timestamp};
+
+    fn datagram(payload: &'static [u8]) -> Datagram {
+        Datagram {
+            payload: Bytes::from_static(payload),
+            sender: "127.0.0.1:6994".parse().unwrap(),
+            destination: None,
+            interface_index: None,
+            interface_name: None,
+            timestamp: Timestamp::Userspace(SystemTime::now()),
+        }
+    }
+
+    #[test]
+    fn it_matches() {
+        let d = datagram(b"\xde\xad\xbe\xef");
+        assert!(Filter::Sender("127.0.0.1:6994".parse().unwrap()).matches(&d));
+        assert!(!Filter::SenderIp("127.0.0.2".parse().unwrap()).matches(&d));
+        assert!(Filter::SenderPort(6994).matches(&d));
+        assert!(Filter::Prefix(Bytes::from_static(b"\xde\xad")).matches(&d));
+        assert!(!Filter::Prefix(Bytes::from_static(b"\xbe")).matches(&d));
+        assert!(Filter::Len(4..=8).matches(&d));
+        assert!(!Filter::Len(0..=3).matches(&d));
+        assert!(Filter::func(|d| d.payload.len() == 4).matches(&d));
+        assert!(Filter::All(vec![Filter::SenderPort(6994), Filter::Len(4..=4)]).matches(&d));
+        assert!(!Filter::All(vec![Filter::SenderPort(6994), Filter::Len(0..=0)]).matches(&d));
+        assert!(Filter::Any(vec![Filter::SenderPort(1), Filter::Len(4..=4)]).matches(&d));
+    }
+
+    #[test]
+    fn it_masks() {
+        let d = datagram(b"\xde\xad\xbe\xef");
+        let mask = |offset, mask: &'static [u8], value: &'static [u8]| Filter::Mask {
+            offset,
+            mask: Bytes::from_static(mask),
+            value: Bytes::from_static(value),
+        };
+        assert!(mask(1, b"\xf0\xff", b"\xa0\xbe").matches(&d));
+        assert!(!mask(1, b"\xff", b"\xa0").matches(&d));
+        //a mask running past the end of the payload never matches
+        assert!(!mask(3, b"\x00\x00", b"\x00\x00").matches(&d));
+    }
+}
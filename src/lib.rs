@@ -1,31 +1,271 @@
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::collections::BTreeSet;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::sync::Arc;
+use std::time::SystemTime;
 
+use bytes::Bytes;
+
+pub mod conflict;
 pub mod connection;
+mod distribution;
+pub mod error;
+pub mod filter;
+pub mod options;
+pub mod parse;
+mod pool;
+pub mod publisher;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod stats;
+pub mod subscription;
+mod sys;
 pub mod udpmanager;
 
+pub use error::{Error, Result};
+
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Datagram {
-    pub payload: Vec<u8>,
-    pub sender: SocketAddr
+    /// shares its buffer with every other subscriber's copy of the datagram. hex in text formats
+    #[cfg_attr(feature = "serde", serde(with = "serialize::payload"))]
+    pub payload: Bytes,
+    pub sender: SocketAddr,
+    /// the address the datagram was sent to, e.g. the multicast group or the broadcast address
+    pub destination: Option<IpAddr>,
+    /// index of the interface the datagram arrived on
+    pub interface_index: Option<u32>,
+    pub interface_name: Option<Arc<str>>,
+    pub timestamp: Timestamp
+}
+
+/// when a datagram was received
+#[derive(PartialEq,Eq,Clone,Copy,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Timestamp {
+    /// taken by the kernel as the datagram came in, see `ConnectionOptions::timestamps`
+    Kernel(SystemTime),
+    /// taken by rudi right after the datagram was read from the socket
+    Userspace(SystemTime)
+}
+
+impl Timestamp {
+    pub fn time(&self) -> SystemTime {
+        match self {
+            Timestamp::Kernel(time) | Timestamp::Userspace(time) => *time,
+        }
+    }
+}
+
+/// which senders of a multicast group are received. sets so the order sources are listed in doesn't matter for deduplication
+#[derive(PartialEq,Eq,Hash,Clone,Debug,Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum SourceFilter {
+    /// any-source multicast
+    #[default]
+    Any,
+    /// source-specific multicast, only these sources are received. used for the 232.0.0.0/8 ssm range
+    Include(BTreeSet<Ipv4Addr>),
+    /// any-source multicast with these sources blocked
+    Exclude(BTreeSet<Ipv4Addr>)
 }
 
+/// the interface a multicast group is joined on, serialized as just the name or address
 #[derive(PartialEq,Eq,Hash,Clone,Debug)]
+pub enum Interface {
+    /// by one of its addresses, `0.0.0.0` lets the kernel pick
+    Addr(Ipv4Addr),
+    /// by name, e.g. `eth1`. looked up when subscribing, so a changed address is picked up
+    Name(String)
+}
+
+impl From<Ipv4Addr> for Interface {
+    fn from(addr: Ipv4Addr) -> Self {
+        Interface::Addr(addr)
+    }
+}
+
+#[derive(PartialEq,Eq,Hash,Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MulticastConfig {
     pub group: Ipv4Addr,
-    pub interface: Ipv4Addr
+    #[cfg_attr(feature = "serde", serde(default = "serialize::any_interface"))]
+    pub interface: Interface,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sources: SourceFilter
 }
 
 #[derive(PartialEq,Eq,Hash,Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
 pub enum CastMode {
+    /// connected to a single peer, only its datagrams are received
     Unicast(SocketAddrV4),
+    /// an unconnected unicast listener that receives from every sender
+    AnySource,
     Broadcast,
     Multicast(MulticastConfig)
 }
 
 #[derive(PartialEq,Eq,Hash,Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IpConfigV4{
     pub cast_mode: CastMode,
-    pub bind_addr: SocketAddrV4
+    pub bind_addr: SocketAddrV4,
+    /// name of the network device (or VRF) to bind to with SO_BINDTODEVICE, e.g. `eth1`.
+    /// the socket then only receives from and sends out of that device
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub device: Option<String>,
+    /// whether other sockets may bind the same address, `None` picks by cast mode: exclusive for
+    /// unicast and any-source so nobody else can steal their datagrams, reuse-address for broadcast and multicast
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sharing: Option<SocketSharing>
+}
+
+/// what other sockets are allowed on a bound address
+#[derive(PartialEq,Eq,Hash,Clone,Copy,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum SocketSharing {
+    /// no other socket can bind the address
+    Exclusive,
+    /// SO_REUSEADDR, other SO_REUSEADDR sockets can bind it too. broadcast and multicast are delivered to all of them
+    ReuseAddress,
+    /// SO_REUSEPORT, other SO_REUSEPORT sockets of the same user can bind it and unicast is balanced between them
+    ReusePort
+}
+
+#[derive(PartialEq,Eq,Hash,Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MulticastConfigV6 {
+    /// the scope is taken from the group itself, e.g. `ff02::` is link-local and `ff05::` is site-local
+    pub group: Ipv6Addr,
+    /// index of the interface to join on, `0` lets the kernel pick one
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub interface: u32
+}
+
+#[derive(PartialEq,Eq,Hash,Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum CastModeV6 {
+    /// connected to a single peer, only its datagrams are received
+    Unicast(SocketAddrV6),
+    /// an unconnected unicast listener that receives from every sender
+    AnySource,
+    Multicast(MulticastConfigV6)
+}
+
+#[derive(PartialEq,Eq,Hash,Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IpConfigV6{
+    pub cast_mode: CastModeV6,
+    pub bind_addr: SocketAddrV6
+}
+
+/// either an ipv4 or an ipv6 config, this is what connections are deduplicated on
+#[derive(PartialEq,Eq,Hash,Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum IpConfig {
+    V4(IpConfigV4),
+    V6(IpConfigV6)
+}
+
+impl IpConfig {
+    pub fn bind_addr(&self) -> SocketAddr {
+        match self {
+            IpConfig::V4(config) => config.bind_addr.into(),
+            IpConfig::V6(config) => config.bind_addr.into(),
+        }
+    }
+
+    /// the peer a `Unicast` config is connected to
+    pub(crate) fn unicast_peer(&self) -> Option<SocketAddr> {
+        match self {
+            IpConfig::V4(IpConfigV4 { cast_mode: CastMode::Unicast(addr), .. }) => Some((*addr).into()),
+            IpConfig::V6(IpConfigV6 { cast_mode: CastModeV6::Unicast(addr), .. }) => Some((*addr).into()),
+            _ => None,
+        }
+    }
+
+    pub fn device(&self) -> Option<&str> {
+        match self {
+            IpConfig::V4(config) => config.device.as_deref(),
+            IpConfig::V6(_) => None,
+        }
+    }
+
+    pub fn sharing(&self) -> SocketSharing {
+        self.configured_sharing().unwrap_or(if self.unicast_peer().is_some() || self.is_any_source() {
+            SocketSharing::Exclusive
+        } else {
+            SocketSharing::ReuseAddress
+        })
+    }
+
+    //the sharing the config asked for, `None` if it goes by the default of its cast mode
+    pub(crate) fn configured_sharing(&self) -> Option<SocketSharing> {
+        match self {
+            IpConfig::V4(config) => config.sharing,
+            IpConfig::V6(_) => None,
+        }
+    }
+
+    /// unicast and any-source configs on the same bind address and device share a socket
+    pub(crate) fn shares_socket(&self, other: &IpConfig) -> bool {
+        let unicast = |config: &IpConfig| config.unicast_peer().is_some() || config.is_any_source();
+        unicast(self) && unicast(other) && self.bind_addr() == other.bind_addr() && self.device() == other.device()
+    }
+
+    pub(crate) fn is_any_source(&self) -> bool {
+        matches!(
+            self,
+            IpConfig::V4(IpConfigV4 { cast_mode: CastMode::AnySource, .. })
+                | IpConfig::V6(IpConfigV6 { cast_mode: CastModeV6::AnySource, .. })
+        )
+    }
+}
+
+impl From<IpConfigV4> for IpConfig {
+    fn from(config: IpConfigV4) -> Self {
+        IpConfig::V4(config)
+    }
+}
+
+impl From<&IpConfigV4> for IpConfig {
+    fn from(config: &IpConfigV4) -> Self {
+        IpConfig::V4(config.clone())
+    }
+}
+
+impl From<IpConfigV6> for IpConfig {
+    fn from(config: IpConfigV6) -> Self {
+        IpConfig::V6(config)
+    }
+}
+
+impl From<&IpConfigV6> for IpConfig {
+    fn from(config: &IpConfigV6) -> Self {
+        IpConfig::V6(config.clone())
+    }
+}
+
+impl From<&IpConfig> for IpConfig {
+    fn from(config: &IpConfig) -> Self {
+        config.clone()
+    }
+}
+
+/// something that went wrong on a connection, see `UdpManager::events`
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub enum ConnectionEvent {
+    /// `recv`/`recv_from` on the socket failed, e.g. `ConnectionRefused` for an unreachable unicast peer.
+    /// transient errors are retried with a backoff, anything else is followed by `Failed`.
+    /// a datagram that came in truncated is dropped and reported as `InvalidData`
+    RecvError { config: IpConfig, kind: io::ErrorKind },
+    /// a fatal error closed the connection, its subscribers see `Closed`
+    Failed { config: IpConfig, kind: io::ErrorKind },
+    /// a datagram was received but could not be handed to the subscribers
+    DistributeError { config: IpConfig },
+    /// leaving the multicast group failed while tearing the connection down
+    LeaveError { config: IpConfig, kind: io::ErrorKind },
 }
 
 #[cfg(test)]
@@ -38,7 +278,9 @@ mod test{
     fn it_can_hash(){
         let config = IpConfigV4{
             cast_mode: CastMode::Unicast("127.0.0.1:6993".parse::<SocketAddrV4>().unwrap()),
-            bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap()
+            bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+            device: None,
+            sharing: None,
         };
 
         let mut m = HashMap::new();
@@ -48,4 +290,27 @@ mod test{
         assert_eq!(*m.get(&config).unwrap(),2);
         assert_eq!(m.len(),1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn v4_and_v6_configs_are_distinct(){
+        let v4: IpConfig = IpConfigV4{
+            cast_mode: CastMode::Unicast("127.0.0.1:6993".parse::<SocketAddrV4>().unwrap()),
+            bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+            device: None,
+            sharing: None,
+        }.into();
+        let v6: IpConfig = IpConfigV6{
+            cast_mode: CastModeV6::Unicast("[::1]:6993".parse::<SocketAddrV6>().unwrap()),
+            bind_addr: "[::]:6993".parse::<SocketAddrV6>().unwrap()
+        }.into();
+
+        let mut m = HashMap::new();
+        m.insert(v4.clone(), 1);
+        m.insert(v6.clone(), 2);
+        m.insert(IpConfig::from(&v6), 3);
+
+        assert_eq!(m.len(),2);
+        assert_eq!(*m.get(&v6).unwrap(),3);
+        assert_eq!(v6.bind_addr().port(),6993);
+    }
+}
@@ -0,0 +1,64 @@
+use std::net::Ipv4Addr;
+
+/// how a connection is set up. only the first subscriber of a config gets to pick these,
+/// later subscribers share the connection as it is. unicast configs sharing a socket have to
+/// ask for the same options, apart from `channel_size` and `distribution`
+#[derive(PartialEq,Eq,Clone,Debug,Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct ConnectionOptions {
+    /// capacity of the channel datagrams are distributed over, `u16::MAX` when not set
+    pub channel_size: Option<usize>,
+    /// have the kernel timestamp datagrams on receive (SO_TIMESTAMPNS, plus software SO_TIMESTAMPING where available)
+    pub timestamps: bool,
+    /// read up to this many datagrams per wakeup with a single `recvmmsg`, for high rate feeds.
+    /// subscribers still receive them one `Datagram` at a time. every datagram of a batch is read into
+    /// a buffer of the largest datagram size, they are capped at 4 MiB in total, i.e. 64 datagrams
+    pub batch_size: Option<usize>,
+    /// have the kernel coalesce bursts of same-flow datagrams (UDP_GRO). coalesced reads are
+    /// split back into one `Datagram` per datagram
+    pub gro: bool,
+    pub distribution: Distribution,
+    pub socket: SocketOptions,
+}
+
+/// socket level options, all set before the socket is bound. `None` leaves the kernel default.
+/// `UdpManager::socket_options` reads back what the kernel made of them
+#[derive(PartialEq,Eq,Clone,Debug,Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct SocketOptions {
+    /// SO_RCVBUF. linux doubles it for bookkeeping and caps it at `net.core.rmem_max`
+    pub recv_buffer: Option<usize>,
+    /// set `recv_buffer` with SO_RCVBUFFORCE instead, which ignores `rmem_max` but needs CAP_NET_ADMIN
+    pub force_recv_buffer: bool,
+    /// SO_SNDBUF
+    pub send_buffer: Option<usize>,
+    /// IP_TOS, IPV6_TCLASS on v6 sockets. the DSCP goes in the upper six bits, `dscp << 2`
+    pub tos: Option<u32>,
+    /// IP_TTL, IPV6_UNICAST_HOPS on v6 sockets
+    pub ttl: Option<u32>,
+    /// IP_MULTICAST_TTL, IPV6_MULTICAST_HOPS on v6 sockets
+    pub multicast_ttl: Option<u32>,
+    /// IP_MULTICAST_LOOP, IPV6_MULTICAST_LOOP on v6 sockets
+    pub multicast_loop: Option<bool>,
+    /// IP_MULTICAST_IF, the interface multicast is sent from. v4 only, a v6 group's interface is part of its config
+    pub multicast_interface: Option<Ipv4Addr>,
+    /// SO_PRIORITY
+    pub priority: Option<u32>,
+    /// SO_MARK, needs CAP_NET_ADMIN
+    pub mark: Option<u32>,
+}
+
+/// how datagrams are handed to a connection's subscribers
+#[derive(PartialEq,Eq,Clone,Copy,Debug,Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Distribution {
+    /// a subscriber that falls more than `channel_size` datagrams behind misses the oldest ones
+    /// and gets `RecvError::Lagged`
+    #[default]
+    Lossy,
+    /// every subscriber sees every datagram the socket delivered. a full channel stops the socket
+    /// being read until the slowest subscriber catches up, so datagrams are dropped by the kernel instead
+    Lossless,
+    /// subscribers only ever see the most recent datagram, `channel_size` is ignored
+    Latest,
+}
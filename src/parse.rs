@@ -0,0 +1,337 @@
+//! `FromStr` and `Display` for `IpConfigV4` and `CastMode`, so a feed fits in one string
+//!
+//! ```text
+//! udp://0.0.0.0:6993?peer=10.0.0.5:7000   unicast
+//! udp://0.0.0.0:6993                      any-source
+//! udp+bcast://0.0.0.0:6993                broadcast
+//! udp+mcast://224.1.1.100@eth0:6993       multicast, the interface is a name or an address
+//! ```
+//! the query takes `device`, `sharing` (`exclusive`, `reuse-address` or `reuse-port`) and, for multicast,
+//! `bind` for an address to bind other than 0.0.0.0 and a `source` or `exclude` per source to receive
+//! from or block. on its own a `CastMode` is `unicast:<peer>`, `any-source`, `broadcast` or
+//! `multicast:<group>[@<interface>][?source=..]`. values aren't escaped, so they can't hold `&`
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::str::FromStr;
+
+use crate::{CastMode, Interface, IpConfigV4, MulticastConfig, SocketSharing, SourceFilter};
+
+/// which part of the string was wrong, and why
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub struct ParseConfigError {
+    pub field: String,
+    pub value: String,
+    pub reason: &'static str,
+}
+
+impl ParseConfigError {
+    fn new(field: &str, value: &str, reason: &'static str) -> Self {
+        ParseConfigError { field: field.to_string(), value: value.to_string(), reason }
+    }
+}
+
+impl fmt::Display for ParseConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} `{}`: {}", self.field, self.value, self.reason)
+    }
+}
+
+impl Error for ParseConfigError {}
+
+fn value<T: FromStr>(field: &str, value: &str) -> Result<T, ParseConfigError> {
+    value.parse().map_err(|_| ParseConfigError::new(field, value, "not a valid value"))
+}
+
+fn sharing_str(sharing: SocketSharing) -> &'static str {
+    match sharing {
+        SocketSharing::Exclusive => "exclusive",
+        SocketSharing::ReuseAddress => "reuse-address",
+        SocketSharing::ReusePort => "reuse-port",
+    }
+}
+
+fn parse_sharing(s: &str) -> Result<SocketSharing, ParseConfigError> {
+    match s {
+        "exclusive" => Ok(SocketSharing::Exclusive),
+        "reuse-address" => Ok(SocketSharing::ReuseAddress),
+        "reuse-port" => Ok(SocketSharing::ReusePort),
+        _ => Err(ParseConfigError::new("sharing", s, "expected exclusive, reuse-address or reuse-port")),
+    }
+}
+
+#[derive(Default)]
+struct Query {
+    peer: Option<SocketAddrV4>,
+    bind: Option<Ipv4Addr>,
+    device: Option<String>,
+    sharing: Option<SocketSharing>,
+    source: BTreeSet<Ipv4Addr>,
+    exclude: BTreeSet<Ipv4Addr>,
+}
+
+fn once<T>(slot: &mut Option<T>, key: &str, raw: &str, parsed: T) -> Result<(), ParseConfigError> {
+    if slot.replace(parsed).is_some() {
+        return Err(ParseConfigError::new(key, raw, "given more than once"));
+    }
+    Ok(())
+}
+
+impl Query {
+    //`allowed` are the keys that mean something for the cast mode at hand
+    fn parse(query: Option<&str>, allowed: &[&str]) -> Result<Self, ParseConfigError> {
+        let mut q = Query::default();
+        for pair in query.into_iter().flat_map(|query| query.split('&')) {
+            let (key, raw) = pair.split_once('=').ok_or_else(|| ParseConfigError::new("query", pair, "expected key=value"))?;
+            if !allowed.contains(&key) {
+                return Err(ParseConfigError::new(key, raw, "not an option of this cast mode"));
+            }
+            match key {
+                "peer" => once(&mut q.peer, key, raw, value(key, raw)?)?,
+                "bind" => once(&mut q.bind, key, raw, value(key, raw)?)?,
+                "device" => once(&mut q.device, key, raw, raw.to_string())?,
+                "sharing" => once(&mut q.sharing, key, raw, parse_sharing(raw)?)?,
+                "source" => {
+                    q.source.insert(value(key, raw)?);
+                }
+                "exclude" => {
+                    q.exclude.insert(value(key, raw)?);
+                }
+                _ => return Err(ParseConfigError::new(key, raw, "unknown option")),
+            }
+        }
+        if !q.source.is_empty() && !q.exclude.is_empty() {
+            return Err(ParseConfigError::new("exclude", query.unwrap_or_default(), "can't be combined with source"));
+        }
+        Ok(q)
+    }
+
+    fn sources(&mut self) -> SourceFilter {
+        if !self.source.is_empty() {
+            SourceFilter::Include(std::mem::take(&mut self.source))
+        } else if !self.exclude.is_empty() {
+            SourceFilter::Exclude(std::mem::take(&mut self.exclude))
+        } else {
+            SourceFilter::Any
+        }
+    }
+}
+
+//`group[@interface]`
+fn parse_group(s: &str, sources: SourceFilter) -> Result<MulticastConfig, ParseConfigError> {
+    let (group, interface) = match s.split_once('@') {
+        Some((group, "")) => return Err(ParseConfigError::new("interface", group, "missing after @")),
+        Some((group, interface)) => match interface.parse::<Ipv4Addr>() {
+            Ok(addr) => (group, Interface::Addr(addr)),
+            Err(_) => (group, Interface::Name(interface.to_string())),
+        },
+        None => (s, Interface::Addr(Ipv4Addr::UNSPECIFIED)),
+    };
+    let group: Ipv4Addr = value("group", group)?;
+    if !group.is_multicast() {
+        return Err(ParseConfigError::new("group", &group.to_string(), "not a multicast address"));
+    }
+    Ok(MulticastConfig { group, interface, sources })
+}
+
+fn write_group(f: &mut fmt::Formatter<'_>, mcast_config: &MulticastConfig) -> fmt::Result {
+    write!(f, "{}", mcast_config.group)?;
+    match &mcast_config.interface {
+        Interface::Addr(addr) if addr.is_unspecified() => Ok(()),
+        Interface::Addr(addr) => write!(f, "@{}", addr),
+        Interface::Name(name) => write!(f, "@{}", name),
+    }
+}
+
+//query pairs in the order they are written, the caller adds the `?` and `&`s
+fn source_pairs(sources: &SourceFilter) -> Vec<(&'static str, String)> {
+    match sources {
+        SourceFilter::Any => vec![],
+        SourceFilter::Include(sources) => sources.iter().map(|source| ("source", source.to_string())).collect(),
+        SourceFilter::Exclude(sources) => sources.iter().map(|source| ("exclude", source.to_string())).collect(),
+    }
+}
+
+fn write_query(f: &mut fmt::Formatter<'_>, pairs: &[(&str, String)]) -> fmt::Result {
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        write!(f, "{}{}={}", if i == 0 { '?' } else { '&' }, key, value)?;
+    }
+    Ok(())
+}
+
+impl FromStr for CastMode {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("unicast", peer)) => Ok(CastMode::Unicast(value("peer", peer)?)),
+            Some(("multicast", rest)) => {
+                let (group, query) = match rest.split_once('?') {
+                    Some((group, query)) => (group, Some(query)),
+                    None => (rest, None),
+                };
+                let mut query = Query::parse(query, &["source", "exclude"])?;
+                Ok(CastMode::Multicast(parse_group(group, query.sources())?))
+            }
+            None if s == "any-source" => Ok(CastMode::AnySource),
+            None if s == "broadcast" => Ok(CastMode::Broadcast),
+            _ => Err(ParseConfigError::new("cast mode", s, "expected unicast:, any-source, broadcast or multicast:")),
+        }
+    }
+}
+
+impl fmt::Display for CastMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CastMode::Unicast(peer) => write!(f, "unicast:{}", peer),
+            CastMode::AnySource => write!(f, "any-source"),
+            CastMode::Broadcast => write!(f, "broadcast"),
+            CastMode::Multicast(mcast_config) => {
+                write!(f, "multicast:")?;
+                write_group(f, mcast_config)?;
+                write_query(f, &source_pairs(&mcast_config.sources))
+            }
+        }
+    }
+}
+
+impl FromStr for IpConfigV4 {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once("://").ok_or_else(|| ParseConfigError::new("scheme", s, "expected udp://, udp+bcast:// or udp+mcast://"))?;
+        let (authority, query) = match rest.split_once('?') {
+            Some((authority, query)) => (authority, Some(query)),
+            None => (rest, None),
+        };
+        let (cast_mode, bind_addr, query) = match scheme {
+            "udp" | "udp+bcast" => {
+                let allowed: &[&str] = if scheme == "udp" { &["peer", "device", "sharing"] } else { &["device", "sharing"] };
+                let query = Query::parse(query, allowed)?;
+                let cast_mode = match (scheme, query.peer) {
+                    ("udp+bcast", _) => CastMode::Broadcast,
+                    (_, Some(peer)) => CastMode::Unicast(peer),
+                    (_, None) => CastMode::AnySource,
+                };
+                (cast_mode, value("address", authority)?, query)
+            }
+            "udp+mcast" => {
+                let (group, port) = authority.rsplit_once(':').ok_or_else(|| ParseConfigError::new("port", authority, "missing"))?;
+                let mut query = Query::parse(query, &["bind", "device", "sharing", "source", "exclude"])?;
+                let bind_addr = SocketAddrV4::new(query.bind.unwrap_or(Ipv4Addr::UNSPECIFIED), value("port", port)?);
+                (CastMode::Multicast(parse_group(group, query.sources())?), bind_addr, query)
+            }
+            _ => return Err(ParseConfigError::new("scheme", scheme, "expected udp, udp+bcast or udp+mcast")),
+        };
+        Ok(IpConfigV4 { cast_mode, bind_addr, device: query.device, sharing: query.sharing })
+    }
+}
+
+impl fmt::Display for IpConfigV4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pairs = vec![];
+        match &self.cast_mode {
+            CastMode::Unicast(peer) => {
+                write!(f, "udp://{}", self.bind_addr)?;
+                pairs.push(("peer", peer.to_string()));
+            }
+            CastMode::AnySource => write!(f, "udp://{}", self.bind_addr)?,
+            CastMode::Broadcast => write!(f, "udp+bcast://{}", self.bind_addr)?,
+            CastMode::Multicast(mcast_config) => {
+                write!(f, "udp+mcast://")?;
+                write_group(f, mcast_config)?;
+                write!(f, ":{}", self.bind_addr.port())?;
+                if !self.bind_addr.ip().is_unspecified() {
+                    pairs.push(("bind", self.bind_addr.ip().to_string()));
+                }
+                pairs.extend(source_pairs(&mcast_config.sources));
+            }
+        }
+        if let Some(device) = &self.device {
+            pairs.push(("device", device.clone()));
+        }
+        if let Some(sharing) = self.sharing {
+            pairs.push(("sharing", sharing_str(sharing).to_string()));
+        }
+        write_query(f, &pairs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParseConfigError;
+    use crate::{CastMode, Interface, IpConfigV4, MulticastConfig, SocketSharing, SourceFilter};
+
+    #[test]
+    fn it_round_trips() {
+        for s in [
+            "udp://0.0.0.0:6993?peer=10.0.0.5:7000",
+            "udp://127.0.0.1:6993?peer=10.0.0.5:7000&device=eth0&sharing=reuse-address",
+            "udp://0.0.0.0:6993",
+            "udp+bcast://0.0.0.0:6993?sharing=exclusive",
+            "udp+mcast://224.1.1.100:6993",
+            "udp+mcast://224.1.1.100@eth0:6993",
+            "udp+mcast://232.1.1.100@192.0.2.2:6993?bind=232.1.1.100&source=10.0.0.1&source=10.0.0.2&sharing=reuse-port",
+            "udp+mcast://224.1.1.100:6993?exclude=10.0.0.1&device=eth0",
+        ] {
+            let config: IpConfigV4 = s.parse().unwrap();
+            assert_eq!(config.to_string(), s);
+        }
+        for s in ["unicast:10.0.0.5:7000", "any-source", "broadcast", "multicast:224.1.1.100@eth0?source=10.0.0.1"] {
+            let cast_mode: CastMode = s.parse().unwrap();
+            assert_eq!(cast_mode.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn it_round_trips_non_canonical_strings() {
+        for s in [
+            "udp://0.0.0.0:6993?sharing=reuse-port&peer=10.0.0.5:7000",
+            "udp+mcast://224.1.1.100@0.0.0.0:6993",
+            "udp+mcast://232.1.1.100:6993?sharing=exclusive&source=10.0.0.2&bind=232.1.1.100&source=10.0.0.1",
+        ] {
+            let config: IpConfigV4 = s.parse().unwrap();
+            let shown = config.to_string();
+            assert_ne!(shown, s);
+            assert_eq!(shown.parse::<IpConfigV4>().unwrap(), config);
+        }
+    }
+
+    #[test]
+    fn it_parses() {
+        let config: IpConfigV4 = "udp+mcast://224.1.1.100@eth0:6993?source=10.0.0.1&sharing=reuse-port".parse().unwrap();
+        assert_eq!(config, IpConfigV4 {
+            cast_mode: CastMode::Multicast(MulticastConfig {
+                group: "224.1.1.100".parse().unwrap(),
+                interface: Interface::Name("eth0".to_string()),
+                sources: SourceFilter::Include(["10.0.0.1".parse().unwrap()].into()),
+            }),
+            bind_addr: "0.0.0.0:6993".parse().unwrap(),
+            device: None,
+            sharing: Some(SocketSharing::ReusePort),
+        });
+    }
+
+    #[test]
+    fn it_points_at_the_bad_field() {
+        let field = |s: &str| s.parse::<IpConfigV4>().unwrap_err().field;
+        assert_eq!(field("0.0.0.0:6993"), "scheme");
+        assert_eq!(field("tcp://0.0.0.0:6993"), "scheme");
+        assert_eq!(field("udp://0.0.0.0"), "address");
+        assert_eq!(field("udp://0.0.0.0:6993?peer=10.0.0.5"), "peer");
+        assert_eq!(field("udp://0.0.0.0:6993?peer=10.0.0.5:1&peer=10.0.0.6:1"), "peer");
+        assert_eq!(field("udp://0.0.0.0:6993?source=10.0.0.1"), "source");
+        assert_eq!(field("udp://0.0.0.0:6993?sharing=all"), "sharing");
+        assert_eq!(field("udp://0.0.0.0:6993?device"), "query");
+        assert_eq!(field("udp+bcast://0.0.0.0:6993?peer=10.0.0.5:7000"), "peer");
+        assert_eq!(field("udp+mcast://10.1.1.100:6993"), "group");
+        assert_eq!(field("udp+mcast://224.1.1.100@:6993"), "interface");
+        assert_eq!(field("udp+mcast://224.1.1.100:port"), "port");
+        assert_eq!(field("udp+mcast://224.1.1.100:6993?source=10.0.0.1&exclude=10.0.0.2"), "exclude");
+        let err = "udp+mcast://224.1.1.100:6993?bind=any".parse::<IpConfigV4>().unwrap_err();
+        assert_eq!(err, ParseConfigError { field: "bind".to_string(), value: "any".to_string(), reason: "not a valid value" });
+        assert_eq!("multicast".parse::<CastMode>().unwrap_err().field, "cast mode");
+    }
+}
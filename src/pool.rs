@@ -0,0 +1,100 @@
+use std::mem::MaybeUninit;
+
+use bytes::{Bytes, BytesMut};
+
+/// receive buffer that datagrams are split off of as `Bytes`, so handing one to every subscriber is
+/// a refcount bump instead of a copy. once every datagram split off a chunk has been dropped the
+/// chunk is reused for the next reads, otherwise a new one is allocated
+pub(crate) struct BufferPool {
+    buf: BytesMut,
+    segment: usize,
+    chunk: usize,
+}
+
+impl BufferPool {
+    /// `segment` is the most a single read can return, a chunk has room for `segments` of them
+    pub(crate) fn new(segment: usize, segments: usize) -> Self {
+        let chunk = segment * segments;
+        BufferPool { buf: BytesMut::with_capacity(chunk), segment, chunk }
+    }
+
+    /// room for the next read
+    pub(crate) fn spare(&mut self) -> &mut [MaybeUninit<u8>] {
+        if self.buf.capacity() < self.segment {
+            self.buf.reserve(self.chunk);
+        }
+        &mut self.buf.spare_capacity_mut()[..self.segment]
+    }
+
+    /// splits off the first `len` bytes of `spare`
+    ///
+    /// # Safety
+    /// those bytes must have been written
+    pub(crate) unsafe fn filled(&mut self, len: usize) -> Bytes {
+        self.buf.set_len(len);
+        self.buf.split().freeze()
+    }
+
+    /// a copy of `data` split off the pool, for reads that went into a buffer of their own
+    pub(crate) fn copy_from(&mut self, data: &[u8]) -> Bytes {
+        if self.buf.capacity() < data.len() {
+            self.buf.reserve(self.chunk);
+        }
+        self.buf.extend_from_slice(data);
+        self.buf.split().freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BufferPool;
+
+    fn read(pool: &mut BufferPool, data: &[u8]) -> bytes::Bytes {
+        let spare = pool.spare();
+        for (dst, src) in spare.iter_mut().zip(data) {
+            dst.write(*src);
+        }
+        unsafe { pool.filled(data.len()) }
+    }
+
+    #[test]
+    fn datagrams_share_a_chunk() {
+        let mut pool = BufferPool::new(8, 4);
+        let first = read(&mut pool, b"dead");
+        let second = read(&mut pool, b"beef");
+        assert_eq!(first, &b"dead"[..]);
+        assert_eq!(second, &b"beef"[..]);
+        assert_eq!(unsafe { first.as_ptr().add(4) }, second.as_ptr());
+    }
+
+    #[test]
+    fn chunk_is_reused_once_released() {
+        let mut pool = BufferPool::new(8, 2);
+        let first = read(&mut pool, &[1; 8]);
+        let start = first.as_ptr();
+        drop(first);
+        let _ = read(&mut pool, &[2; 8]);
+        //the chunk is used up and nobody holds on to it anymore
+        assert_eq!(read(&mut pool, &[3; 8]).as_ptr(), start);
+    }
+
+    #[test]
+    fn copies_share_a_chunk() {
+        let mut pool = BufferPool::new(8, 4);
+        let first = pool.copy_from(b"dead");
+        let second = pool.copy_from(b"beef");
+        assert_eq!(first, &b"dead"[..]);
+        assert_eq!(second, &b"beef"[..]);
+        //only as much of the chunk as the data needs
+        assert_eq!(unsafe { first.as_ptr().add(4) }, second.as_ptr());
+    }
+
+    #[test]
+    fn held_chunk_is_not_overwritten() {
+        let mut pool = BufferPool::new(8, 1);
+        let first = read(&mut pool, &[1; 8]);
+        let second = read(&mut pool, &[2; 8]);
+        assert_eq!(first, &[1u8; 8][..]);
+        assert_eq!(second, &[2u8; 8][..]);
+    }
+}
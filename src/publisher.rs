@@ -0,0 +1,73 @@
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::sync::Arc;
+
+use tokio::io;
+use tokio::net::UdpSocket;
+
+use crate::error::{Error, Result};
+use crate::{CastMode, CastModeV6, IpConfig};
+
+/// sends on a connection's socket, to wherever its cast mode points
+pub struct Publisher {
+    config: IpConfig,
+    socket: Arc<UdpSocket>,
+    //`None` for any-source sockets, they have nowhere to send by default
+    destination: Option<SocketAddr>,
+    broadcast: bool,
+}
+
+impl Publisher {
+    pub(crate) fn new(ip_config: &IpConfig, socket: Arc<UdpSocket>) -> Self {
+        let port = ip_config.bind_addr().port();
+        let (destination, broadcast) = match ip_config {
+            IpConfig::V4(config) => match &config.cast_mode {
+                //unicast peers can share a socket, so it isn't necessarily connected
+                CastMode::Unicast(addr) => (Some((*addr).into()), false),
+                CastMode::AnySource => (None, false),
+                CastMode::Broadcast => (Some(SocketAddrV4::new(Ipv4Addr::BROADCAST, port).into()), true),
+                CastMode::Multicast(mcast_config) => (Some(SocketAddrV4::new(mcast_config.group, port).into()), false),
+            },
+            IpConfig::V6(config) => match &config.cast_mode {
+                CastModeV6::Unicast(addr) => (Some((*addr).into()), false),
+                CastModeV6::AnySource => (None, false),
+                //the scope id picks the interface for link-local groups
+                CastModeV6::Multicast(mcast_config) => {
+                    (Some(SocketAddrV6::new(mcast_config.group, port, 0, mcast_config.interface).into()), false)
+                }
+            },
+        };
+        Publisher { config: ip_config.clone(), socket, destination, broadcast }
+    }
+
+    /// where datagrams are sent, `None` for `AnySource` which can only `send_to`
+    pub fn destination(&self) -> Option<SocketAddr> {
+        self.destination
+    }
+
+    pub async fn send(&self, payload: &[u8]) -> Result<usize> {
+        if self.broadcast && !self.socket.broadcast().map_err(|source| self.error(source))? {
+            return Err(self.error(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "broadcast socket doesn't have SO_BROADCAST set",
+            )));
+        }
+        match self.destination {
+            Some(addr) => self.socket.send_to(payload, addr).await.map_err(|source| self.error(source)),
+            None => Err(self.error(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "any-source sockets have no default destination, use send_to",
+            ))),
+        }
+    }
+
+    /// sends to `addr` instead of the cast mode's destination, e.g. to reply to an any-source sender.
+    /// it goes out on the same socket even when that is connected to a single unicast peer, but
+    /// replies from `addr` are only received if a subscribed config on the socket takes its datagrams
+    pub async fn send_to(&self, payload: &[u8], addr: SocketAddr) -> Result<usize> {
+        self.socket.send_to(payload, addr).await.map_err(|source| self.error(source))
+    }
+
+    fn error(&self, source: io::Error) -> Error {
+        Error::Io { config: self.config.clone(), source }
+    }
+}
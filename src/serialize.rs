@@ -0,0 +1,166 @@
+//! the parts of the `serde` feature derives can't do. interfaces are a plain name or address, and
+//! payloads are hex in text formats like json and toml and raw bytes in binary ones
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::Interface;
+
+pub(crate) fn any_interface() -> Interface {
+    Interface::Addr(Ipv4Addr::UNSPECIFIED)
+}
+
+impl Serialize for Interface {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Interface::Addr(addr) => serializer.collect_str(addr),
+            Interface::Name(name) => serializer.serialize_str(name),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Interface {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(match s.parse::<Ipv4Addr>() {
+            Ok(addr) => Interface::Addr(addr),
+            Err(_) => Interface::Name(s),
+        })
+    }
+}
+
+pub(crate) mod payload {
+    use super::*;
+    use bytes::Bytes;
+
+    pub(crate) fn serialize<S: Serializer>(payload: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_bytes(payload);
+        }
+        let hex: String = payload.iter().map(|b| format!("{:02x}", b)).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(PayloadVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(PayloadVisitor)
+        }
+    }
+
+    struct PayloadVisitor;
+
+    impl<'de> Visitor<'de> for PayloadVisitor {
+        type Value = Bytes;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a hex string or bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, hex: &str) -> Result<Bytes, E> {
+            //is_multiple_of would need rust 1.87
+            #[allow(clippy::manual_is_multiple_of)]
+            let odd = hex.len() % 2 != 0;
+            if odd {
+                return Err(E::invalid_length(hex.len(), &"an even number of hex digits"));
+            }
+            //from_str_radix alone would take a sign
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(E::invalid_value(de::Unexpected::Str(hex), &self));
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(hex.get(i..i + 2).unwrap_or_default(), 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map(Bytes::from)
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(hex), &self))
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Bytes, E> {
+            Ok(Bytes::copy_from_slice(bytes))
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Bytes, E> {
+            Ok(Bytes::from(bytes))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use bytes::Bytes;
+    use serde_json::json;
+
+    use crate::options::{ConnectionOptions, Distribution};
+    use crate::{CastMode, Datagram, Interface, IpConfig, IpConfigV4, MulticastConfig, SocketSharing, SourceFilter, Timestamp};
+
+    #[test]
+    fn configs_read_naturally() {
+        let config: IpConfigV4 = serde_json::from_value(json!({
+            "cast_mode": { "multicast": { "group": "232.1.1.100", "interface": "eth0", "sources": { "include": ["10.0.0.1"] } } },
+            "bind_addr": "0.0.0.0:6993",
+            "sharing": "reuse-port",
+        }))
+        .unwrap();
+        assert_eq!(config, IpConfigV4 {
+            cast_mode: CastMode::Multicast(MulticastConfig {
+                group: "232.1.1.100".parse().unwrap(),
+                interface: Interface::Name("eth0".to_string()),
+                sources: SourceFilter::Include(["10.0.0.1".parse().unwrap()].into()),
+            }),
+            bind_addr: "0.0.0.0:6993".parse().unwrap(),
+            device: None,
+            sharing: Some(SocketSharing::ReusePort),
+        });
+
+        //left out interface and sources mean any
+        let config: IpConfig = serde_json::from_value(json!({
+            "v4": { "cast_mode": { "multicast": { "group": "224.1.1.100" } }, "bind_addr": "0.0.0.0:6993" }
+        }))
+        .unwrap();
+        let back = serde_json::to_value(&config).unwrap();
+        assert_eq!(back["v4"]["cast_mode"]["multicast"], json!({ "group": "224.1.1.100", "interface": "0.0.0.0", "sources": "any" }));
+        assert_eq!(serde_json::from_value::<IpConfig>(back).unwrap(), config);
+
+        let unicast = serde_json::to_value(CastMode::Unicast("10.0.0.5:7000".parse().unwrap())).unwrap();
+        assert_eq!(unicast, json!({ "unicast": "10.0.0.5:7000" }));
+        assert_eq!(serde_json::to_value(CastMode::AnySource).unwrap(), json!("any-source"));
+
+        let options: ConnectionOptions = serde_json::from_value(json!({ "distribution": "lossless", "socket": { "recv_buffer": 1 } })).unwrap();
+        assert_eq!(options.distribution, Distribution::Lossless);
+        assert_eq!(options.socket.recv_buffer, Some(1));
+    }
+
+    #[test]
+    fn payloads_are_hex() {
+        let datagram = Datagram {
+            payload: Bytes::from_static(b"\xde\xad\xbe\xef"),
+            sender: "127.0.0.1:6994".parse().unwrap(),
+            destination: None,
+            interface_index: Some(1),
+            interface_name: Some("lo".into()),
+            timestamp: Timestamp::Kernel(SystemTime::UNIX_EPOCH + Duration::from_secs(1)),
+        };
+        let value = serde_json::to_value(&datagram).unwrap();
+        assert_eq!(value["payload"], json!("deadbeef"));
+        let back: Datagram = serde_json::from_value(value).unwrap();
+        assert_eq!(back.payload, datagram.payload);
+        assert_eq!(back.interface_name.as_deref(), Some("lo"));
+        assert_eq!(back.timestamp, datagram.timestamp);
+        //everything but the payload is valid
+        let payload = |hex: &str| {
+            let mut value = serde_json::to_value(&datagram).unwrap();
+            value["payload"] = json!(hex);
+            serde_json::from_value::<Datagram>(value)
+        };
+        assert!(payload("deadbeef").is_ok());
+        assert!(payload("dea").is_err());
+        assert!(payload("+f").is_err());
+    }
+}
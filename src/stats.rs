@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// counters a connection's recv task and subscriptions update as they go
+#[derive(Default)]
+pub(crate) struct Counters {
+    datagrams: AtomicU64,
+    bytes: AtomicU64,
+    dropped: AtomicU64,
+    recv_errors: AtomicU64,
+    lagged: AtomicU64,
+    //nanoseconds since the unix epoch, 0 until the first datagram
+    last_datagram: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn datagram(&self, bytes: usize) {
+        self.datagrams.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.last_datagram.store(now.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn recv_error(&self) {
+        self.recv_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn lagged(&self, skipped: u64) {
+        self.lagged.fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, subscribers: usize) -> ConnectionStats {
+        let last_datagram = match self.last_datagram.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(UNIX_EPOCH + Duration::from_nanos(nanos)),
+        };
+        ConnectionStats {
+            datagrams: self.datagrams.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            recv_errors: self.recv_errors.load(Ordering::Relaxed),
+            subscribers,
+            last_datagram,
+            lagged: self.lagged.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// a point in time copy of a connection's counters, see `UdpManager::stats`
+#[derive(PartialEq,Eq,Clone,Debug,Default)]
+pub struct ConnectionStats {
+    /// datagrams read from the socket
+    pub datagrams: u64,
+    /// payload bytes read from the socket
+    pub bytes: u64,
+    /// datagrams read from the socket that no subscriber was around to receive
+    pub dropped: u64,
+    /// failed reads, plus datagrams dropped for coming in truncated
+    pub recv_errors: u64,
+    pub subscribers: usize,
+    pub last_datagram: Option<SystemTime>,
+    /// datagrams subscribers missed because they fell behind, summed over all subscribers
+    pub lagged: u64,
+}
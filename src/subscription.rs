@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+
+use crate::distribution::Receiver;
+use crate::{connection::Shared, Datagram};
+
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub enum RecvError {
+    /// the connection was torn down and every datagram it received has been handed out
+    Closed,
+    /// the subscriber fell behind a lossy channel and missed this many datagrams
+    Lagged(u64),
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Closed => write!(f, "connection closed"),
+            RecvError::Lagged(skipped) => write!(f, "subscriber lagged by {skipped} datagrams"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub enum TryRecvError {
+    /// no datagram is waiting
+    Empty,
+    Closed,
+    Lagged(u64),
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "no datagram waiting"),
+            TryRecvError::Closed => write!(f, "connection closed"),
+            TryRecvError::Lagged(skipped) => write!(f, "subscriber lagged by {skipped} datagrams"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+/// a subscriber's handle on a connection, dropping the last one tears the connection down
+pub struct Subscription {
+    rx: Receiver,
+    shared: Arc<Shared>,
+    lagged: u64,
+}
+
+impl Subscription {
+    pub(crate) fn new(rx: Receiver, shared: Arc<Shared>) -> Self {
+        Subscription { rx, shared, lagged: 0 }
+    }
+
+    pub async fn recv(&mut self) -> Result<Datagram, RecvError> {
+        let received = match &mut self.rx {
+            Receiver::Lossy(rx) => rx.recv().await.map_err(|e| match e {
+                broadcast::error::RecvError::Closed => RecvError::Closed,
+                broadcast::error::RecvError::Lagged(skipped) => RecvError::Lagged(skipped),
+            }),
+            Receiver::Lossless(rx) => rx.recv().await.map_err(|e| match e {
+                async_broadcast::RecvError::Closed => RecvError::Closed,
+                async_broadcast::RecvError::Overflowed(skipped) => RecvError::Lagged(skipped),
+            }),
+            Receiver::Latest(rx) => match rx.changed().await {
+                Ok(()) => rx.borrow_and_update().clone().ok_or(RecvError::Closed),
+                Err(_) => Err(RecvError::Closed),
+            },
+        };
+        if let Err(RecvError::Lagged(skipped)) = received {
+            self.record_lag(skipped);
+        }
+        received
+    }
+
+    pub fn try_recv(&mut self) -> Result<Datagram, TryRecvError> {
+        let received = match &mut self.rx {
+            Receiver::Lossy(rx) => rx.try_recv().map_err(|e| match e {
+                broadcast::error::TryRecvError::Empty => TryRecvError::Empty,
+                broadcast::error::TryRecvError::Closed => TryRecvError::Closed,
+                broadcast::error::TryRecvError::Lagged(skipped) => TryRecvError::Lagged(skipped),
+            }),
+            Receiver::Lossless(rx) => rx.try_recv().map_err(|e| match e {
+                async_broadcast::TryRecvError::Empty => TryRecvError::Empty,
+                async_broadcast::TryRecvError::Closed => TryRecvError::Closed,
+                async_broadcast::TryRecvError::Overflowed(skipped) => TryRecvError::Lagged(skipped),
+            }),
+            Receiver::Latest(rx) => match rx.has_changed() {
+                Ok(true) => rx.borrow_and_update().clone().ok_or(TryRecvError::Empty),
+                Ok(false) => Err(TryRecvError::Empty),
+                Err(_) => Err(TryRecvError::Closed),
+            },
+        };
+        if let Err(TryRecvError::Lagged(skipped)) = received {
+            self.record_lag(skipped);
+        }
+        received
+    }
+
+    /// how many datagrams this subscriber has missed by falling behind
+    pub fn lagged(&self) -> u64 {
+        self.lagged
+    }
+
+    fn record_lag(&mut self, skipped: u64) {
+        self.lagged += skipped;
+        self.shared.counters.lagged(skipped);
+    }
+
+    /// datagrams waiting to be received, at most 1 for `Distribution::Latest`
+    pub fn len(&self) -> usize {
+        match &self.rx {
+            Receiver::Lossy(rx) => rx.len(),
+            Receiver::Lossless(rx) => rx.len(),
+            Receiver::Latest(rx) => rx.has_changed().unwrap_or(false) as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("len", &self.len())
+            .field("lagged", &self.lagged)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.shared.release();
+    }
+}
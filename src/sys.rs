@@ -0,0 +1,389 @@
+//! socket options and calls neither tokio nor socket2 expose
+
+use std::ffi::{CStr, CString};
+use std::mem::{self, MaybeUninit};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::fd::AsRawFd;
+use std::ptr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::io;
+
+//room for every control message we ask for, u64s to keep it aligned for cmsghdr
+const CONTROL_LEN: usize = 256;
+
+fn in_addr(addr: Ipv4Addr) -> libc::in_addr {
+    libc::in_addr { s_addr: u32::from_ne_bytes(addr.octets()) }
+}
+
+fn setsockopt<T>(socket: &impl AsRawFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn getsockopt<T: Copy>(socket: &impl AsRawFd, level: libc::c_int, name: libc::c_int) -> io::Result<T> {
+    let mut value = MaybeUninit::<T>::zeroed();
+    let mut len = mem::size_of::<T>() as libc::socklen_t;
+    let ret = unsafe { libc::getsockopt(socket.as_raw_fd(), level, name, value.as_mut_ptr() as *mut libc::c_void, &mut len) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { value.assume_init() })
+}
+
+/// SO_RCVBUFFORCE, a receive buffer size that isn't capped by `net.core.rmem_max`
+pub(crate) fn set_recv_buffer_force(socket: &impl AsRawFd, size: usize) -> io::Result<()> {
+    let size = libc::c_int::try_from(size).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "receive buffer too large"))?;
+    setsockopt(socket, libc::SOL_SOCKET, libc::SO_RCVBUFFORCE, &size)
+}
+
+/// the queueing priority of packets sent on the socket (SO_PRIORITY)
+pub(crate) fn set_priority(socket: &impl AsRawFd, priority: u32) -> io::Result<()> {
+    setsockopt(socket, libc::SOL_SOCKET, libc::SO_PRIORITY, &(priority as libc::c_int))
+}
+
+pub(crate) fn priority(socket: &impl AsRawFd) -> io::Result<u32> {
+    getsockopt::<libc::c_int>(socket, libc::SOL_SOCKET, libc::SO_PRIORITY).map(|priority| priority as u32)
+}
+
+/// stops receiving `source`'s traffic on an any-source membership of `group` (IP_BLOCK_SOURCE)
+pub(crate) fn block_source_v4(socket: &impl AsRawFd, group: Ipv4Addr, interface: Ipv4Addr, source: Ipv4Addr) -> io::Result<()> {
+    let mreq = libc::ip_mreq_source {
+        imr_multiaddr: in_addr(group),
+        imr_interface: in_addr(interface),
+        imr_sourceaddr: in_addr(source),
+    };
+    setsockopt(socket, libc::IPPROTO_IP, libc::IP_BLOCK_SOURCE, &mreq)
+}
+
+/// only deliver multicast for groups this socket joined itself. by default a wildcard bound socket
+/// also gets every group any other socket on the host joined on its port (IP_MULTICAST_ALL/IPV6_MULTICAST_ALL)
+pub(crate) fn disable_multicast_all(socket: &impl AsRawFd, ipv6: bool) -> io::Result<()> {
+    let off: libc::c_int = 0;
+    if ipv6 {
+        setsockopt(socket, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_ALL, &off)
+    } else {
+        setsockopt(socket, libc::IPPROTO_IP, libc::IP_MULTICAST_ALL, &off)
+    }
+}
+
+/// asks for the destination address and ingress interface of every datagram (IP_PKTINFO/IPV6_RECVPKTINFO)
+pub(crate) fn enable_pktinfo(socket: &impl AsRawFd, ipv6: bool) -> io::Result<()> {
+    let on: libc::c_int = 1;
+    if ipv6 {
+        setsockopt(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, &on)
+    } else {
+        setsockopt(socket, libc::IPPROTO_IP, libc::IP_PKTINFO, &on)
+    }
+}
+
+/// has the kernel timestamp every datagram on receive. SO_TIMESTAMPNS is required,
+/// software SO_TIMESTAMPING is added on top where the kernel supports it
+pub(crate) fn enable_timestamps(socket: &impl AsRawFd) -> io::Result<()> {
+    let on: libc::c_int = 1;
+    setsockopt(socket, libc::SOL_SOCKET, libc::SO_TIMESTAMPNS, &on)?;
+    let flags = (libc::SOF_TIMESTAMPING_RX_SOFTWARE | libc::SOF_TIMESTAMPING_SOFTWARE) as libc::c_int;
+    let _ = setsockopt(socket, libc::SOL_SOCKET, libc::SO_TIMESTAMPING, &flags);
+    Ok(())
+}
+
+/// lets the kernel hand over bursts of same-flow datagrams as one coalesced buffer (UDP_GRO)
+pub(crate) fn enable_gro(socket: &impl AsRawFd) -> io::Result<()> {
+    let on: libc::c_int = 1;
+    setsockopt(socket, libc::SOL_UDP, libc::UDP_GRO, &on)
+}
+
+/// dissolves the peer association of a connected socket, it keeps its bound address
+pub(crate) fn disconnect(socket: &impl AsRawFd) -> io::Result<()> {
+    let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    addr.ss_family = libc::AF_UNSPEC as libc::sa_family_t;
+    let ret = unsafe {
+        libc::connect(
+            socket.as_raw_fd(),
+            &addr as *const _ as *const libc::sockaddr,
+            mem::size_of_val(&addr) as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// what `recv_msg` learned about a datagram besides its payload
+#[derive(Clone)]
+pub(crate) struct RecvMeta {
+    pub(crate) len: usize,
+    pub(crate) sender: SocketAddr,
+    pub(crate) destination: Option<IpAddr>,
+    pub(crate) interface_index: Option<u32>,
+    pub(crate) timestamp: Option<SystemTime>,
+    //taken by the reader right after the receive call returned, for datagrams the kernel didn't stamp
+    pub(crate) received: Option<SystemTime>,
+    //size of the datagrams a coalesced GRO buffer is made of
+    pub(crate) segment_size: Option<usize>,
+    //the datagram didn't fit the buffer and `len` is only what was kept of it (MSG_TRUNC)
+    pub(crate) truncated: bool,
+}
+
+fn system_time(ts: &libc::timespec) -> Option<SystemTime> {
+    if ts.tv_sec == 0 && ts.tv_nsec == 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+fn socket_addr(name: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    match name.ss_family as libc::c_int {
+        libc::AF_INET => {
+            let addr = unsafe { &*(name as *const _ as *const libc::sockaddr_in) };
+            Ok(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                u16::from_be(addr.sin_port),
+            )
+            .into())
+        }
+        libc::AF_INET6 => {
+            let addr = unsafe { &*(name as *const _ as *const libc::sockaddr_in6) };
+            Ok(SocketAddrV6::new(
+                Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                u16::from_be(addr.sin6_port),
+                u32::from_be(addr.sin6_flowinfo),
+                addr.sin6_scope_id,
+            )
+            .into())
+        }
+        family => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected address family {family}"))),
+    }
+}
+
+/// everything `recvmsg` fills in besides the payload
+struct MsgBuffers {
+    name: libc::sockaddr_storage,
+    control: [u64; CONTROL_LEN / 8],
+}
+
+impl MsgBuffers {
+    fn new() -> Self {
+        unsafe { mem::zeroed() }
+    }
+
+    fn msghdr(&mut self, iov: &mut libc::iovec) -> libc::msghdr {
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut self.name as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of_val(&self.name) as libc::socklen_t;
+        msg.msg_iov = iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = self.control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&self.control) as _;
+        msg
+    }
+}
+
+//reads the sender and control messages of a msghdr `recvmsg`/`recvmmsg` has filled in
+fn recv_meta(msg: &libc::msghdr, len: usize) -> io::Result<RecvMeta> {
+    let name = unsafe { &*(msg.msg_name as *const libc::sockaddr_storage) };
+    let mut meta = RecvMeta {
+        len,
+        sender: socket_addr(name)?,
+        destination: None,
+        interface_index: None,
+        timestamp: None,
+        received: None,
+        segment_size: None,
+        truncated: msg.msg_flags & libc::MSG_TRUNC != 0,
+    };
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(msg);
+        while !cmsg.is_null() {
+            match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                    let info = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::in_pktinfo);
+                    meta.destination = Some(Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr)).into());
+                    meta.interface_index = Some(info.ipi_ifindex as u32);
+                }
+                (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                    let info = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::in6_pktinfo);
+                    meta.destination = Some(Ipv6Addr::from(info.ipi6_addr.s6_addr).into());
+                    meta.interface_index = Some(info.ipi6_ifindex);
+                }
+                //SO_TIMESTAMPING is preferred when both are there
+                (libc::SOL_SOCKET, libc::SCM_TIMESTAMPNS) if meta.timestamp.is_none() => {
+                    let ts = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec);
+                    meta.timestamp = system_time(&ts);
+                }
+                (libc::SOL_SOCKET, libc::SO_TIMESTAMPING) => {
+                    //software, deprecated and hardware stamps, only software is asked for
+                    let ts = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const [libc::timespec; 3]);
+                    if let Some(time) = system_time(&ts[0]) {
+                        meta.timestamp = Some(time);
+                    }
+                }
+                (libc::SOL_UDP, libc::UDP_GRO) => {
+                    let size = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                    meta.segment_size = usize::try_from(size).ok().filter(|&size| size > 0);
+                }
+                _ => {}
+            }
+            cmsg = libc::CMSG_NXTHDR(msg, cmsg);
+        }
+    }
+    Ok(meta)
+}
+
+/// a non-blocking `recvmsg` that also reads the control messages enabled on the socket.
+/// the first `len` bytes of `buf` are initialized when it returns
+pub(crate) fn recv_msg(socket: &impl AsRawFd, buf: &mut [MaybeUninit<u8>]) -> io::Result<RecvMeta> {
+    let mut buffers = MsgBuffers::new();
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg = buffers.msghdr(&mut iov);
+
+    let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if len == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    recv_meta(&msg, len as usize)
+}
+
+/// the most datagrams a single `recvmmsg` reads, the kernel caps it at UIO_MAXIOV
+pub(crate) const MAX_BATCH: usize = libc::UIO_MAXIOV as usize;
+//every datagram of a batch needs a slot of the largest size, this bounds what they add up to
+pub(crate) const MAX_BATCH_BYTES: usize = 4 << 20;
+
+/// preallocated buffers for reading up to `count` datagrams with a single `recvmmsg`, they are
+/// reused for every read so payloads have to be copied out
+pub(crate) struct RecvBatch {
+    buf: Box<[u8]>,
+    segment: usize,
+    buffers: Vec<MsgBuffers>,
+    metas: Vec<RecvMeta>,
+}
+
+impl RecvBatch {
+    pub(crate) fn new(segment: usize, count: usize) -> Self {
+        let count = count.min(MAX_BATCH).min(MAX_BATCH_BYTES / segment).max(1);
+        RecvBatch {
+            buf: vec![0u8; segment * count].into_boxed_slice(),
+            segment,
+            buffers: (0..count).map(|_| MsgBuffers::new()).collect(),
+            metas: Vec::with_capacity(count),
+        }
+    }
+
+    /// a non-blocking `recvmmsg`, returns how many datagrams were read
+    pub(crate) fn recv(&mut self, socket: &impl AsRawFd) -> io::Result<usize> {
+        let mut iovs: Vec<libc::iovec> = self
+            .buf
+            .chunks_mut(self.segment)
+            .map(|chunk| libc::iovec {
+                iov_base: chunk.as_mut_ptr() as *mut libc::c_void,
+                iov_len: chunk.len(),
+            })
+            .collect();
+        let mut msgs: Vec<libc::mmsghdr> = self
+            .buffers
+            .iter_mut()
+            .zip(iovs.iter_mut())
+            .map(|(buffers, iov)| libc::mmsghdr { msg_hdr: buffers.msghdr(iov), msg_len: 0 })
+            .collect();
+
+        let count = unsafe {
+            libc::recvmmsg(socket.as_raw_fd(), msgs.as_mut_ptr(), msgs.len() as libc::c_uint, 0, ptr::null_mut())
+        };
+        if count == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.metas.clear();
+        for msg in &msgs[..count as usize] {
+            self.metas.push(recv_meta(&msg.msg_hdr, msg.msg_len as usize)?);
+        }
+        Ok(self.metas.len())
+    }
+
+    /// the `i`th datagram of the last `recv`
+    pub(crate) fn datagram(&self, i: usize) -> (&RecvMeta, &[u8]) {
+        let meta = &self.metas[i];
+        let start = i * self.segment;
+        (meta, &self.buf[start..start + meta.len])
+    }
+}
+
+/// index of the interface called `name`
+pub(crate) fn interface_index(name: &str) -> io::Result<u32> {
+    let c_name = CString::new(name).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "interface name contains a nul byte"))?;
+    let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("no interface named {name}")));
+    }
+    Ok(index)
+}
+
+/// the first ipv4 address of the interface called `name`
+pub(crate) fn interface_addr_v4(name: &str) -> io::Result<Ipv4Addr> {
+    let mut addrs: *mut libc::ifaddrs = ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let (mut exists, mut found) = (false, None);
+    let mut cur = addrs;
+    while !cur.is_null() && found.is_none() {
+        let ifa = unsafe { &*cur };
+        if unsafe { CStr::from_ptr(ifa.ifa_name) }.to_bytes() == name.as_bytes() {
+            exists = true;
+            if !ifa.ifa_addr.is_null() && unsafe { (*ifa.ifa_addr).sa_family } as libc::c_int == libc::AF_INET {
+                let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                found = Some(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)));
+            }
+        }
+        cur = ifa.ifa_next;
+    }
+    unsafe { libc::freeifaddrs(addrs) };
+    match found {
+        Some(addr) => Ok(addr),
+        None if exists => Err(io::Error::new(io::ErrorKind::AddrNotAvailable, format!("interface {name} has no ipv4 address"))),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, format!("no interface named {name}"))),
+    }
+}
+
+pub(crate) fn interface_name(index: u32) -> Option<String> {
+    let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+    let ret = unsafe { libc::if_indextoname(index, name.as_mut_ptr()) };
+    if ret.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::MaybeUninit;
+    use std::net::UdpSocket;
+
+    use super::recv_msg;
+
+    #[test]
+    fn reports_truncation() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(b"deadbeef", socket.local_addr().unwrap()).unwrap();
+        let mut buf = [MaybeUninit::new(0u8); 4];
+        let meta = recv_msg(&socket, &mut buf).unwrap();
+        assert_eq!(meta.len, 4);
+        assert!(meta.truncated);
+    }
+}
@@ -1,45 +1,170 @@
 use std::{collections::HashMap, sync::Arc};
 
-use tokio::sync::broadcast::Receiver;
 use tokio::io;
+use tokio::sync::broadcast::{self, Receiver, Sender};
 
-use crate::{connection::Connection, Datagram, IpConfigV4};
+use crate::{conflict::{self, Conflict}, connection::Connection, error::{Error, Result}, filter::Filter, options::{ConnectionOptions, SocketOptions}, publisher::Publisher, stats::ConnectionStats, subscription::Subscription, ConnectionEvent, IpConfig};
 
-#[derive(Default)]
+const EVENT_CHANNEL_SIZE: usize = 1024;
+
+/// dropping the manager stops every recv task, use `shutdown` to also wait for them to finish
 pub struct UdpManager {
-    connections: HashMap<IpConfigV4, Connection>,
+    connections: HashMap<IpConfig, Connection>,
+    events: Sender<ConnectionEvent>,
+    shut_down: bool,
+}
+
+impl Default for UdpManager {
+    fn default() -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_SIZE);
+        UdpManager {
+            connections: HashMap::new(),
+            events,
+            shut_down: false,
+        }
+    }
 }
 
 impl UdpManager {
-    pub async fn subscribe(&mut self, ip_config: &IpConfigV4, channel_size: Option<usize>) -> io::Result<Receiver<Datagram>> {
-
-        let conn = if let Some(conn) = self.connections.get(ip_config) {
-            conn
-        } else {
-            let (tx,_) = if let Some(size) = channel_size {
-                // async_broadcast::broadcast::<Datagram>(size)
-                tokio::sync::broadcast::channel(size)
-            }else{
-                // async_broadcast::broadcast::<Datagram>(u16::MAX as usize)
-                tokio::sync::broadcast::channel(u16::MAX as usize)
-            };
-            let c = Connection::new(&ip_config,tx).await?;
-            self.connections.insert(ip_config.clone(), c);
-            self.connections.get(ip_config).unwrap()
+    /// accepts an `IpConfigV4`, an `IpConfigV6` or an `IpConfig`, by value or by reference
+    pub async fn subscribe(&mut self, ip_config: impl Into<IpConfig>, channel_size: Option<usize>) -> Result<Subscription> {
+        self.subscribe_with(ip_config, &ConnectionOptions { channel_size, ..Default::default() }).await
+    }
+
+    /// like `subscribe`, `options` only take effect if this creates the connection
+    pub async fn subscribe_with(&mut self, ip_config: impl Into<IpConfig>, options: &ConnectionOptions) -> Result<Subscription> {
+        self.subscribe_via(ip_config.into(), options, Connection::subscribe).await
+    }
+
+    /// a subscription that only receives the datagrams matching `filter`. the filter runs in the recv task,
+    /// so datagrams it rejects are never queued for this subscriber. a connection it has to open gets the default options
+    pub async fn subscribe_filtered(&mut self, ip_config: impl Into<IpConfig>, filter: Filter) -> Result<Subscription> {
+        self.subscribe_filtered_with(ip_config, filter, &ConnectionOptions::default()).await
+    }
+
+    /// like `subscribe_filtered`, `options` only take effect if this creates the connection
+    pub async fn subscribe_filtered_with(&mut self, ip_config: impl Into<IpConfig>, filter: Filter, options: &ConnectionOptions) -> Result<Subscription> {
+        self.subscribe_via(ip_config.into(), options, |conn| conn.subscribe_filtered(filter.clone())).await
+    }
+
+    async fn subscribe_via(
+        &mut self,
+        ip_config: IpConfig,
+        options: &ConnectionOptions,
+        subscribe: impl Fn(&Connection) -> Option<Subscription>,
+    ) -> Result<Subscription> {
+        if self.shut_down {
+            return Err(Error::ShutDown { config: ip_config });
+        }
+
+        if let Some(conn) = self.connections.get(&ip_config) {
+            if let Some(subscription) = subscribe(conn) {
+                return Ok(subscription);
+            }
+            //the last subscriber just left, wait for the old socket to close before binding a new one
+            if let Some(conn) = self.connections.remove(&ip_config) {
+                conn.close().await;
+            }
+        }
+        //sockets that aren't shared have to be gone before their address can be bound again
+        let closed: Vec<IpConfig> = self.connections.iter().filter(|(_, conn)| conn.is_closed()).map(|(config, _)| config.clone()).collect();
+        for config in closed {
+            if let Some(conn) = self.connections.remove(&config) {
+                conn.close().await;
+            }
+        }
+
+        //rather than let the kernel pick which of two overlapping sockets gets a datagram
+        let conflict = self.connections.keys().find_map(|existing| {
+            conflict::check(&ip_config, existing).map(|reason| Conflict { config: ip_config.clone(), existing: existing.clone(), reason })
+        });
+        if let Some(conflict) = conflict {
+            return Err(Error::AddrInUse { config: ip_config, source: io::Error::new(io::ErrorKind::AddrInUse, conflict) });
+        }
+
+        //unicast configs on a bind address that is already open join its socket
+        let attached = match self.connections.iter().find(|(config, conn)| config.shares_socket(&ip_config) && !conn.is_closed()) {
+            Some((_, conn)) => conn.attach(&ip_config, options)?,
+            None => None,
         };
+        let conn = match attached {
+            Some(conn) => conn,
+            None => Connection::new(&ip_config,options,self.events.clone()).await?,
+        };
+        let subscription = subscribe(&conn).ok_or_else(|| Error::Io {
+            config: ip_config.clone(),
+            source: io::Error::new(io::ErrorKind::NotConnected, "connection closed before it could be subscribed to"),
+        })?;
+        self.connections.insert(ip_config, conn);
+
+        Ok(subscription)
+    }
+
+    /// tears down the connection for `ip_config` regardless of how many subscribers it has,
+    /// their streams end with `Closed`. returns false if there was no open connection
+    pub async fn unsubscribe(&mut self, ip_config: impl Into<IpConfig>) -> bool {
+        match self.connections.remove(&ip_config.into()) {
+            Some(conn) => {
+                let was_open = !conn.is_closed();
+                conn.close().await;
+                was_open
+            }
+            None => false,
+        }
+    }
 
-        Ok(conn.subscribe())
+    /// stops every recv task and waits for them to close their sockets. every subscriber's stream
+    /// ends with `Closed` and later calls to `subscribe` fail
+    pub async fn shutdown(&mut self) {
+        self.shut_down = true;
+        let connections: Vec<Connection> = self.connections.drain().map(|(_, conn)| conn).collect();
+        for conn in connections {
+            conn.close().await;
+        }
+    }
+
+    /// errors from every connection, only events sent after this call are received
+    pub fn events(&self) -> Receiver<ConnectionEvent> {
+        self.events.subscribe()
     }
 
     pub fn count(&self) -> usize {
-        self.connections.len()
+        self.connections.values().filter(|conn| !conn.is_closed()).count()
     }
 
-    pub fn get_socket(&self, config: &IpConfigV4) -> Option<Arc<tokio::net::UdpSocket>> {
-        if let Some(conn) = self.connections.get(config){
-            Some(conn.socket.clone())
-        }else{
-            None
+    /// counters for every open connection
+    pub fn stats(&self) -> HashMap<IpConfig, ConnectionStats> {
+        self.connections
+            .iter()
+            .filter(|(_, conn)| !conn.is_closed())
+            .map(|(config, conn)| (config.clone(), conn.stats()))
+            .collect()
+    }
+
+    /// the socket options of the connection for `config` as the kernel applied them
+    pub fn socket_options(&self, config: impl Into<IpConfig>) -> Result<SocketOptions> {
+        let config = config.into();
+        match self.connections.get(&config).and_then(|conn| conn.socket_options()) {
+            Some(options) => options.map_err(|source| Error::Io { config, source }),
+            None => Err(Error::NotSubscribed { config }),
         }
-    } 
+    }
+
+    /// a handle that sends on the socket already opened for `config`, fails if it isn't subscribed
+    pub fn publisher(&self, config: impl Into<IpConfig>) -> Result<Publisher> {
+        let config = config.into();
+        match self.connections.get(&config).and_then(|conn| conn.socket()) {
+            Some(socket) => Ok(Publisher::new(&config, socket)),
+            None => Err(Error::NotSubscribed { config }),
+        }
+    }
+
+    /// sends `payload` to the peer, broadcast address or multicast group of `config`
+    pub async fn send(&self, config: impl Into<IpConfig>, payload: &[u8]) -> Result<usize> {
+        self.publisher(config)?.send(payload).await
+    }
+
+    pub fn get_socket(&self, config: impl Into<IpConfig>) -> Option<Arc<tokio::net::UdpSocket>> {
+        self.connections.get(&config.into()).and_then(|conn| conn.socket())
+    }
 }
@@ -1,7 +1,10 @@
+//the original tests are kept as written, in a style clippy would flag
+#![allow(clippy::manual_ok_err, clippy::let_underscore_future, clippy::len_zero)]
+
 use serial_test::serial;
 use tokio::net::UdpSocket;
-use std::net::{Ipv4Addr, SocketAddrV4};
-use rudi::{udpmanager::UdpManager, CastMode, IpConfigV4, MulticastConfig};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use rudi::{udpmanager::UdpManager, CastMode, Error, CastModeV6, ConnectionEvent, Interface, IpConfigV4, IpConfigV6, MulticastConfig, MulticastConfigV6, SocketSharing, SourceFilter};
 
 //tests have the `serial` attribute so they dont fail due to port conflicts
 
@@ -12,6 +15,8 @@ async fn example_usage() {
     let unicast = IpConfigV4 {
         cast_mode: unicast(),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&unicast,None).await.unwrap();
 
@@ -31,10 +36,40 @@ async fn sharing_sockets_not_allowed() {
     let unicast = IpConfigV4 {
         cast_mode: unicast(),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
-    udp.subscribe(&unicast,None).await.unwrap();
+    let _rx1 = udp.subscribe(&unicast,None).await.unwrap();
 
     assert!(UdpSocket::bind("0.0.0.0:6993").await.is_err());
+    //not even a socket that asks to share the address
+    assert!(co_bind("0.0.0.0:6993", true, true).is_err());
+}
+
+//binds the way another process trying to share the address would
+fn co_bind(addr: &str, reuse_address: bool, reuse_port: bool) -> std::io::Result<socket2::Socket> {
+    let sock = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None)?;
+    sock.set_reuse_address(reuse_address)?;
+    sock.set_reuse_port(reuse_port)?;
+    sock.bind(&addr.parse::<std::net::SocketAddr>().unwrap().into())?;
+    Ok(sock)
+}
+
+#[rstest]
+#[case::unicast_default(config(unicast(),"0.0.0.0:6993"), [false, false, false])]
+#[case::any_source_default(config(CastMode::AnySource,"0.0.0.0:6993"), [false, false, false])]
+#[case::broadcast_default(config(CastMode::Broadcast,"0.0.0.0:6993"), [true, false, true])]
+#[case::exclusive_broadcast(IpConfigV4 { sharing: Some(SocketSharing::Exclusive), ..config(CastMode::Broadcast,"0.0.0.0:6993") }, [false, false, false])]
+#[case::reuse_port_unicast(IpConfigV4 { sharing: Some(SocketSharing::ReusePort), ..config(unicast(),"0.0.0.0:6993") }, [false, true, true])]
+#[tokio::test]
+#[serial]
+async fn socket_sharing_policy(#[case] ip_config: IpConfigV4, #[case] allowed: [bool; 3]) {
+    let mut udp = UdpManager::default();
+    let _rx1 = udp.subscribe(&ip_config,None).await.unwrap();
+
+    assert_eq!(co_bind("0.0.0.0:6993", true, false).is_ok(), allowed[0]);
+    assert_eq!(co_bind("0.0.0.0:6993", false, true).is_ok(), allowed[1]);
+    assert_eq!(co_bind("0.0.0.0:6993", true, true).is_ok(), allowed[2]);
 }
 
 use rstest::*;
@@ -43,6 +78,8 @@ fn config(mode: CastMode, addr: &str) -> IpConfigV4 {
     IpConfigV4 {
         cast_mode: mode,
         bind_addr: addr.parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     }
 }
 
@@ -53,14 +90,15 @@ fn unicast() -> CastMode {
 fn multicast() -> CastMode {
     CastMode::Multicast(MulticastConfig{
         group: "225.1.1.100".parse::<Ipv4Addr>().unwrap(),
-        interface: "0.0.0.0".parse::<Ipv4Addr>().unwrap(),
+        interface: Interface::Addr("0.0.0.0".parse::<Ipv4Addr>().unwrap()),
+        sources: SourceFilter::Any,
     })
 }
 
 #[rstest]
 #[case::same_type_same_iface_port_unicast((config(unicast(),"0.0.0.0:6993"),config(unicast(),"0.0.0.0:6993")),1)]
 #[case::same_type_same_iface_diff_port_unicast((config(unicast(),"0.0.0.0:6993"),config(unicast(),"0.0.0.0:6994")),2)]
-#[case::diff_type_same_iface_port_diff_proto((config(unicast(),"0.0.0.0:6993"),config(multicast(),"0.0.0.0:6993")),2)]
+#[case::diff_type_same_iface_port_diff_proto((IpConfigV4 { sharing: Some(SocketSharing::ReuseAddress), ..config(unicast(),"0.0.0.0:6993") },config(multicast(),"0.0.0.0:6993")),2)]
 #[case::diff_type_same_iface_diff_port_diff_proto((config(unicast(),"0.0.0.0:6993"),config(multicast(),"0.0.0.0:6994")),2)]
 #[case::same_type_same_iface_port_multicast((config(multicast(),"0.0.0.0:6994"),config(multicast(),"0.0.0.0:6994")),1)]
 #[case::same_type_same_iface_diff_port_multicast((config(multicast(),"0.0.0.0:6993"),config(multicast(),"0.0.0.0:6994")),2)]
@@ -68,8 +106,8 @@ fn multicast() -> CastMode {
 #[serial]
 async fn test_permutations(#[case] conns: (IpConfigV4, IpConfigV4), #[case] result: usize) {
     let mut udp = UdpManager::default();
-    udp.subscribe(&conns.0,None).await.unwrap();
-    udp.subscribe(&conns.1,None).await.unwrap();
+    let _rx1 = udp.subscribe(&conns.0,None).await.unwrap();
+    let _rx2 = udp.subscribe(&conns.1,None).await.unwrap();
     assert_eq!(udp.count(), result);
 }
 
@@ -80,6 +118,8 @@ async fn test_rx_data_unicast() {
     let unicast = IpConfigV4 {
         cast_mode: unicast(),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&unicast,None).await.unwrap();
 
@@ -98,7 +138,7 @@ async fn test_rx_data_unicast() {
 
     let r = h.await.unwrap().unwrap();
 
-    assert_eq!(r.payload, data);
+    assert_eq!(r.payload, &data[..]);
 }
 
 #[tokio::test]
@@ -108,6 +148,8 @@ async fn test_rx_data_broadcast_all() {
     let broadcast = IpConfigV4 {
         cast_mode: CastMode::Broadcast,
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&broadcast,None).await.unwrap();
 
@@ -126,7 +168,7 @@ async fn test_rx_data_broadcast_all() {
 
     let r = h.await.unwrap().unwrap();
 
-    assert_eq!(r.payload, data);
+    assert_eq!(r.payload, &data[..]);
 }
 
 #[tokio::test]
@@ -136,6 +178,8 @@ async fn test_cant_broadcast_on_unicast() {
     let broadcast = IpConfigV4 {
         cast_mode: unicast(),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&broadcast,None).await.unwrap();
 
@@ -161,10 +205,13 @@ async fn test_rx_data_multicast() {
         cast_mode: CastMode::Multicast(
             MulticastConfig{ 
                 group: "225.1.1.100".parse::<Ipv4Addr>().unwrap(), 
-                interface: "0.0.0.0".parse::<Ipv4Addr>().unwrap() 
+                interface: Interface::Addr("0.0.0.0".parse::<Ipv4Addr>().unwrap()),
+                sources: SourceFilter::Any,
             }
         ),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&mcast,None).await.unwrap();
 
@@ -183,7 +230,7 @@ async fn test_rx_data_multicast() {
 
     let r = h.await.unwrap().unwrap();
 
-    assert_eq!(r.payload, data);
+    assert_eq!(r.payload, &data[..]);
 }
 
 use tokio::runtime::Runtime;
@@ -195,6 +242,8 @@ fn call_from_sync(){
     let unicast = IpConfigV4 {
         cast_mode: unicast(),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let rt = Runtime::new().unwrap();
     let mut rx1 = rt.block_on(udp.subscribe(&unicast,None)).unwrap();
@@ -214,7 +263,7 @@ fn call_from_sync(){
 
     let r = rt.block_on(h).unwrap().unwrap();
 
-    assert_eq!(r.payload, data);
+    assert_eq!(r.payload, &data[..]);
 }
 
 #[tokio::test]
@@ -224,6 +273,8 @@ async fn test_multiple_rx_data_unicast() {
     let unicast = IpConfigV4 {
         cast_mode: unicast(),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&unicast,None).await.unwrap();
     let mut rx2 = udp.subscribe(&unicast,None).await.unwrap();
@@ -251,8 +302,8 @@ async fn test_multiple_rx_data_unicast() {
 
     let (r1,r2) = tokio::join!(h1,h2);
 
-    assert_eq!(r1.unwrap().unwrap().payload, data);
-    assert_eq!(r2.unwrap().unwrap().payload, data);
+    assert_eq!(r1.unwrap().unwrap().payload, &data[..]);
+    assert_eq!(r2.unwrap().unwrap().payload, &data[..]);
 }
 
 #[tokio::test]
@@ -262,10 +313,14 @@ async fn test_diff_rx_data_unicast() {
     let unicast1 = IpConfigV4 {
         cast_mode: CastMode::Unicast("127.0.0.1:6993".parse::<SocketAddrV4>().unwrap()),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let unicast2 = IpConfigV4 {
         cast_mode: CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),
         bind_addr: "0.0.0.0:6994".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&unicast1,None).await.unwrap();
     let mut rx2 = udp.subscribe(&unicast2,None).await.unwrap();
@@ -296,8 +351,8 @@ async fn test_diff_rx_data_unicast() {
 
     let (r1,r2) = tokio::join!(h1,h2);
 
-    assert_eq!(r1.unwrap().unwrap().payload, data);
-    assert_eq!(r2.unwrap().unwrap().payload, data);
+    assert_eq!(r1.unwrap().unwrap().payload, &data[..]);
+    assert_eq!(r2.unwrap().unwrap().payload, &data[..]);
 }
 
 #[tokio::test]
@@ -307,9 +362,12 @@ async fn test_multiple_rx_data_multicast() {
     let mcast = IpConfigV4 {
         cast_mode: CastMode::Multicast(MulticastConfig{ 
             group: "224.1.1.100".parse::<Ipv4Addr>().unwrap(), 
-            interface: "0.0.0.0".parse::<Ipv4Addr>().unwrap() 
+            interface: Interface::Addr("0.0.0.0".parse::<Ipv4Addr>().unwrap()),
+            sources: SourceFilter::Any,
         }),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&mcast,None).await.unwrap();
     let mut rx2 = udp.subscribe(&mcast,None).await.unwrap();
@@ -337,8 +395,8 @@ async fn test_multiple_rx_data_multicast() {
 
     let (r1,r2) = tokio::join!(h1,h2);
 
-    assert_eq!(r1.unwrap().unwrap().payload, data);
-    assert_eq!(r2.unwrap().unwrap().payload, data);
+    assert_eq!(r1.unwrap().unwrap().payload, &data[..]);
+    assert_eq!(r2.unwrap().unwrap().payload, &data[..]);
 }
 
 #[tokio::test]
@@ -348,16 +406,22 @@ async fn test_diff_rx_data_multicast() {
     let mcast1 = IpConfigV4 {
         cast_mode: CastMode::Multicast(MulticastConfig{ 
             group: "224.1.1.100".parse::<Ipv4Addr>().unwrap(), 
-            interface: "0.0.0.0".parse::<Ipv4Addr>().unwrap() 
+            interface: Interface::Addr("0.0.0.0".parse::<Ipv4Addr>().unwrap()),
+            sources: SourceFilter::Any,
         }),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mcast2 = IpConfigV4 {
         cast_mode: CastMode::Multicast(MulticastConfig{ 
             group: "224.1.1.200".parse::<Ipv4Addr>().unwrap(), 
-            interface: "0.0.0.0".parse::<Ipv4Addr>().unwrap() 
+            interface: Interface::Addr("0.0.0.0".parse::<Ipv4Addr>().unwrap()),
+            sources: SourceFilter::Any,
         }),
         bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
     let mut rx1 = udp.subscribe(&mcast1,None).await.unwrap();
     let mut rx2 = udp.subscribe(&mcast2,None).await.unwrap();
@@ -388,8 +452,8 @@ async fn test_diff_rx_data_multicast() {
 
     let (r1,r2) = tokio::join!(h1,h2);
 
-    assert_eq!(r1.unwrap().unwrap().payload, data);
-    assert_eq!(r2.unwrap().unwrap().payload, data);
+    assert_eq!(r1.unwrap().unwrap().payload, &data[..]);
+    assert_eq!(r2.unwrap().unwrap().payload, &data[..]);
 }
 
 #[tokio::test]
@@ -399,6 +463,8 @@ async fn test_new_subscriptions_should_not_receive_old_data() {
     let broadcast = IpConfigV4 {
         cast_mode: CastMode::Broadcast,
         bind_addr: "127.0.0.1:6993".parse::<SocketAddrV4>().unwrap(),
+        device: None,
+        sharing: None,
     };
 
     let data = b"deadbeef";
@@ -416,4 +482,1139 @@ async fn test_new_subscriptions_should_not_receive_old_data() {
     let mut rx2 = udp.subscribe(&broadcast,None).await.unwrap();
 
     assert!(rx2.try_recv().is_err());
-}
\ No newline at end of file
+}
+
+fn config_v6(mode: CastModeV6, addr: &str) -> IpConfigV6 {
+    IpConfigV6 {
+        cast_mode: mode,
+        bind_addr: addr.parse::<SocketAddrV6>().unwrap(),
+    }
+}
+
+fn unicast_v6() -> CastModeV6 {
+    CastModeV6::Unicast("[::1]:6993".parse::<SocketAddrV6>().unwrap())
+}
+
+fn multicast_v6(group: &str) -> CastModeV6 {
+    CastModeV6::Multicast(MulticastConfigV6{
+        group: group.parse::<Ipv6Addr>().unwrap(),
+        interface: 0,
+    })
+}
+
+#[tokio::test]
+#[serial]
+async fn test_permutations_v6() {
+    let mut udp = UdpManager::default();
+    let _rx1 = udp.subscribe(&config_v6(unicast_v6(),"[::]:6993"),None).await.unwrap();
+    let _rx2 = udp.subscribe(&config_v6(unicast_v6(),"[::]:6993"),None).await.unwrap();
+    assert_eq!(udp.count(), 1);
+
+    let _rx3 = udp.subscribe(&config_v6(multicast_v6("ff02::6993"),"[::]:6994"),None).await.unwrap();
+    let _rx4 = udp.subscribe(&config_v6(multicast_v6("ff02::6993"),"[::]:6994"),None).await.unwrap();
+    assert_eq!(udp.count(), 2);
+
+    //same port as the v6 unicast config but a different address family
+    let _rx5 = udp.subscribe(&config(unicast(),"0.0.0.0:6993"),None).await.unwrap();
+    assert_eq!(udp.count(), 3);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_rx_data_unicast_v6() {
+    let mut udp = UdpManager::default();
+    let unicast = config_v6(unicast_v6(),"[::]:6993");
+    let mut rx1 = udp.subscribe(&unicast,None).await.unwrap();
+
+    let h = tokio::spawn(async move {
+        if let Ok(data) = rx1.recv().await {
+            Some(data)
+        }else{
+            None
+        }
+    });
+
+    let data = b"deadbeef";
+
+    let sock = udp.get_socket(&unicast).unwrap();
+    sock.send(data).await.unwrap();
+
+    let r = h.await.unwrap().unwrap();
+
+    assert_eq!(r.payload, &data[..]);
+    assert_eq!(r.sender, "[::1]:6993".parse().unwrap());
+}
+
+#[rstest]
+#[case::single(None)]
+#[case::batched(Some(8))]
+#[tokio::test]
+#[serial]
+async fn largest_v6_datagram_arrives_whole(#[case] batch_size: Option<usize>) {
+    let mut udp = UdpManager::default();
+    let any = config_v6(CastModeV6::AnySource,"[::1]:6993");
+    let options = ConnectionOptions { batch_size, ..Default::default() };
+    let mut rx1 = udp.subscribe_with(&any,&options).await.unwrap();
+
+    //past the v4 limit of 65507
+    let data = vec![0xab; 65527];
+    let peer = UdpSocket::bind("[::1]:6994").await.unwrap();
+    peer.send_to(&data,"[::1]:6993").await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(1), rx1.recv()).await.unwrap().unwrap();
+    assert_eq!(received.payload.len(), data.len());
+    assert_eq!(udp.stats()[&any.into()].recv_errors, 0);
+}
+
+#[rstest]
+#[case::link_local("ff02::6993")]
+#[case::site_local("ff05::6993")]
+#[tokio::test]
+#[serial]
+async fn test_rx_data_multicast_v6(#[case] group: &str) {
+    let mut udp = UdpManager::default();
+    let mcast = config_v6(multicast_v6(group),"[::]:6993");
+    let mut rx1 = udp.subscribe(&mcast,None).await.unwrap();
+
+    let h = tokio::spawn(async move {
+        if let Ok(data) = rx1.recv().await {
+            Some(data)
+        }else{
+            None
+        }
+    });
+
+    let data = b"deadbeef";
+
+    let sock = udp.get_socket(&mcast).unwrap();
+    sock.send_to(data,format!("[{group}]:6993")).await.unwrap();
+
+    let r = h.await.unwrap().unwrap();
+
+    assert_eq!(r.payload, &data[..]);
+}
+
+use rudi::subscription::RecvError;
+use std::time::Duration;
+
+//the recv task closes the socket asynchronously once a connection is torn down
+async fn wait_for_port(addr: &str) -> UdpSocket {
+    for _ in 0..100 {
+        if let Ok(sock) = UdpSocket::bind(addr).await {
+            return sock;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{addr} was never released");
+}
+
+#[tokio::test]
+#[serial]
+async fn dropping_last_subscriber_tears_down_connection() {
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"0.0.0.0:6993");
+    let rx1 = udp.subscribe(&unicast,None).await.unwrap();
+    let rx2 = udp.subscribe(&unicast,None).await.unwrap();
+
+    drop(rx1);
+    assert_eq!(udp.count(), 1);
+    assert!(udp.get_socket(&unicast).is_some());
+
+    drop(rx2);
+    assert_eq!(udp.count(), 0);
+    assert!(udp.get_socket(&unicast).is_none());
+
+    drop(wait_for_port("0.0.0.0:6993").await);
+
+    //the same config can be subscribed to again afterwards
+    let _rx3 = udp.subscribe(&unicast,None).await.unwrap();
+    assert_eq!(udp.count(), 1);
+}
+
+#[tokio::test]
+#[serial]
+async fn dropping_last_multicast_subscriber_releases_port() {
+    let mut udp = UdpManager::default();
+    let mcast = config(multicast(),"0.0.0.0:6993");
+    let rx1 = udp.subscribe(&mcast,None).await.unwrap();
+    drop(rx1);
+
+    assert_eq!(udp.count(), 0);
+    drop(wait_for_port("0.0.0.0:6993").await);
+}
+
+#[tokio::test]
+#[serial]
+async fn unsubscribe_closes_subscribers() {
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"0.0.0.0:6993");
+    let mut rx1 = udp.subscribe(&unicast,None).await.unwrap();
+    let mut rx2 = udp.subscribe(&unicast,None).await.unwrap();
+
+    assert!(udp.unsubscribe(&unicast).await);
+    assert!(!udp.unsubscribe(&unicast).await);
+    assert_eq!(udp.count(), 0);
+
+    assert!(matches!(rx1.recv().await, Err(RecvError::Closed)));
+    assert!(matches!(rx2.recv().await, Err(RecvError::Closed)));
+
+    //the socket is already closed once unsubscribe returns
+    assert!(UdpSocket::bind("0.0.0.0:6993").await.is_ok());
+}
+
+#[tokio::test]
+#[serial]
+async fn shutdown_closes_everything() {
+    let mut udp = UdpManager::default();
+    let mut rx1 = udp.subscribe(&config(unicast(),"0.0.0.0:6993"),None).await.unwrap();
+    let mut rx2 = udp.subscribe(&config(multicast(),"0.0.0.0:6994"),None).await.unwrap();
+    let mut rx3 = udp.subscribe(&config_v6(unicast_v6(),"[::]:6995"),None).await.unwrap();
+
+    let h = tokio::spawn(async move {
+        rx1.recv().await
+    });
+
+    udp.shutdown().await;
+    assert_eq!(udp.count(), 0);
+
+    assert!(matches!(h.await.unwrap(), Err(RecvError::Closed)));
+    assert!(matches!(rx2.recv().await, Err(RecvError::Closed)));
+    assert!(matches!(rx3.recv().await, Err(RecvError::Closed)));
+
+    //every socket is closed once shutdown returns
+    assert!(UdpSocket::bind("0.0.0.0:6993").await.is_ok());
+    assert!(UdpSocket::bind("0.0.0.0:6994").await.is_ok());
+    assert!(UdpSocket::bind("[::]:6995").await.is_ok());
+
+    let err = udp.subscribe(&config(unicast(),"0.0.0.0:6993"),None).await.unwrap_err();
+    assert!(matches!(err, Error::ShutDown { .. }));
+}
+
+#[tokio::test]
+#[serial]
+async fn dropping_manager_stops_recv_tasks() {
+    let mut udp = UdpManager::default();
+    let mut rx1 = udp.subscribe(&config(unicast(),"0.0.0.0:6993"),None).await.unwrap();
+
+    drop(udp);
+
+    assert!(matches!(rx1.recv().await, Err(RecvError::Closed)));
+    drop(wait_for_port("0.0.0.0:6993").await);
+}
+
+#[tokio::test]
+#[serial]
+async fn recv_errors_are_reported_as_events() {
+    let mut udp = UdpManager::default();
+    let mut events = udp.events();
+    //nothing listens on the peer port, so the kernel answers with an icmp port unreachable
+    let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"0.0.0.0:6993");
+    let _rx1 = udp.subscribe(&unicast,None).await.unwrap();
+
+    let sock = udp.get_socket(&unicast).unwrap();
+    sock.send(b"deadbeef").await.unwrap();
+
+    let event = tokio::time::timeout(Duration::from_secs(1), events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, ConnectionEvent::RecvError { config: unicast.into(), kind: std::io::ErrorKind::ConnectionRefused });
+}
+
+#[tokio::test]
+#[serial]
+async fn refused_unicast_peer_backs_off_and_recovers() {
+    let mut udp = UdpManager::default();
+    let mut events = udp.events();
+    let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+    let mut rx1 = udp.subscribe(&unicast,None).await.unwrap();
+
+    //every datagram to the dead peer comes back as an icmp port unreachable
+    let sock = udp.get_socket(&unicast).unwrap();
+    for _ in 0..50 {
+        //a pending refusal can also be reported by the send itself
+        let _ = sock.send(b"deadbeef").await;
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut refused = 0;
+    while let Ok(event) = events.try_recv() {
+        match event {
+            ConnectionEvent::RecvError { kind: std::io::ErrorKind::ConnectionRefused, .. } => refused += 1,
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+    //at most one error per icmp message, a spinning loop would report far more
+    assert!((1..=50).contains(&refused), "{refused} errors");
+
+    //refusals are transient, once the peer comes up its data is delivered
+    assert_eq!(udp.count(), 1);
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    peer.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+
+    let r = tokio::time::timeout(Duration::from_secs(2), rx1.recv()).await.unwrap().unwrap();
+    assert_eq!(r.payload, &b"deadbeef"[..]);
+}
+
+use rudi::stats::ConnectionStats;
+
+async fn wait_for_stats(udp: &UdpManager, config: &IpConfigV4, datagrams: u64) -> ConnectionStats {
+    for _ in 0..100 {
+        let stats = udp.stats().remove(&config.into()).unwrap();
+        if stats.datagrams >= datagrams {
+            return stats;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("never received {datagrams} datagrams");
+}
+
+#[tokio::test]
+#[serial]
+async fn stats_count_traffic_and_lag() {
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"127.0.0.1:6993");
+    let mut rx1 = udp.subscribe(&unicast,Some(1)).await.unwrap();
+    let _rx2 = udp.subscribe(&unicast,None).await.unwrap();
+
+    assert_eq!(udp.stats()[&unicast.clone().into()], ConnectionStats { subscribers: 2, ..Default::default() });
+
+    let sock = udp.get_socket(&unicast).unwrap();
+    for _ in 0..3 {
+        sock.send(b"deadbeef").await.unwrap();
+    }
+
+    let stats = wait_for_stats(&udp, &unicast, 3).await;
+    assert_eq!(stats.datagrams, 3);
+    assert_eq!(stats.bytes, 24);
+    assert_eq!(stats.dropped, 0);
+    assert_eq!(stats.recv_errors, 0);
+    assert_eq!(stats.subscribers, 2);
+    assert!(stats.last_datagram.is_some());
+
+    //the channel only holds one datagram, so the first two were overwritten
+    assert!(matches!(rx1.recv().await, Err(RecvError::Lagged(2))));
+    assert!(rx1.recv().await.is_ok());
+    assert_eq!(rx1.lagged(), 2);
+    assert_eq!(udp.stats()[&unicast.clone().into()].lagged, 2);
+}
+
+#[rstest]
+#[case::unicast(config(unicast(),"127.0.0.1:6993").into())]
+#[case::broadcast(config(CastMode::Broadcast,"0.0.0.0:6993").into())]
+#[case::multicast(config(multicast(),"0.0.0.0:6993").into())]
+#[case::unicast_v6(config_v6(unicast_v6(),"[::1]:6993").into())]
+#[case::multicast_v6(config_v6(multicast_v6("ff02::6993"),"[::]:6993").into())]
+#[tokio::test]
+#[serial]
+async fn send_routes_to_cast_mode_destination(#[case] ip_config: rudi::IpConfig) {
+    let mut udp = UdpManager::default();
+    let mut rx1 = udp.subscribe(&ip_config,None).await.unwrap();
+
+    let data = b"deadbeef";
+    assert_eq!(udp.send(&ip_config, data).await.unwrap(), data.len());
+
+    let r = tokio::time::timeout(Duration::from_secs(1), rx1.recv()).await.unwrap().unwrap();
+    assert_eq!(r.payload, &data[..]);
+}
+
+#[tokio::test]
+#[serial]
+async fn send_requires_a_subscription() {
+    let udp = UdpManager::default();
+    let err = udp.send(&config(unicast(),"127.0.0.1:6993"), b"deadbeef").await.unwrap_err();
+    assert!(matches!(err, Error::NotSubscribed { .. }));
+    assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+}
+
+#[tokio::test]
+#[serial]
+async fn send_broadcast_without_so_broadcast_fails() {
+    let mut udp = UdpManager::default();
+    let broadcast = config(CastMode::Broadcast,"0.0.0.0:6993");
+    let _rx1 = udp.subscribe(&broadcast,None).await.unwrap();
+
+    let publisher = udp.publisher(&broadcast).unwrap();
+    assert_eq!(publisher.destination(), Some("255.255.255.255:6993".parse().unwrap()));
+
+    udp.get_socket(&broadcast).unwrap().set_broadcast(false).unwrap();
+    let err = publisher.send(b"deadbeef").await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+}
+
+#[tokio::test]
+#[serial]
+async fn connected_unicast_can_send_elsewhere() {
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"127.0.0.1:6994");
+    let _rx1 = udp.subscribe(&unicast,None).await.unwrap();
+
+    let other = UdpSocket::bind("127.0.0.1:6995").await.unwrap();
+    udp.publisher(&unicast).unwrap().send_to(b"deadbeef", other.local_addr().unwrap()).await.unwrap();
+    let mut buf = [0; 8];
+    let (len, from) = tokio::time::timeout(Duration::from_secs(1), other.recv_from(&mut buf)).await.unwrap().unwrap();
+    assert_eq!(&buf[..len], b"deadbeef");
+    assert_eq!(from, "127.0.0.1:6994".parse().unwrap());
+}
+
+fn multicast_from(group: &str, sources: SourceFilter) -> CastMode {
+    CastMode::Multicast(MulticastConfig{
+        group: group.parse::<Ipv4Addr>().unwrap(),
+        interface: Interface::Addr("0.0.0.0".parse::<Ipv4Addr>().unwrap()),
+        sources,
+    })
+}
+
+//the address multicast from this host is sent from, whatever interface that ends up being
+async fn local_multicast_source() -> Ipv4Addr {
+    let mut udp = UdpManager::default();
+    let mcast = config(multicast_from("232.1.1.100", SourceFilter::Any),"0.0.0.0:6993");
+    let mut rx1 = udp.subscribe(&mcast,None).await.unwrap();
+    udp.send(&mcast, b"deadbeef").await.unwrap();
+    let r = tokio::time::timeout(Duration::from_secs(1), rx1.recv()).await.unwrap().unwrap();
+    udp.shutdown().await;
+    match r.sender {
+        std::net::SocketAddr::V4(addr) => *addr.ip(),
+        std::net::SocketAddr::V6(_) => unreachable!(),
+    }
+}
+
+#[rstest]
+#[case::include_sender(true, true)]
+#[case::include_other(true, false)]
+#[case::exclude_sender(false, true)]
+#[case::exclude_other(false, false)]
+#[tokio::test]
+#[serial]
+async fn test_rx_data_source_specific_multicast(#[case] include: bool, #[case] list_sender: bool) {
+    let source = if list_sender {
+        local_multicast_source().await
+    } else {
+        "192.0.2.99".parse::<Ipv4Addr>().unwrap()
+    };
+    let sources = [source].into_iter().collect();
+    let sources = if include { SourceFilter::Include(sources) } else { SourceFilter::Exclude(sources) };
+
+    let mut udp = UdpManager::default();
+    let mcast = config(multicast_from("232.1.1.100", sources),"0.0.0.0:6993");
+    let mut rx1 = udp.subscribe(&mcast,None).await.unwrap();
+
+    udp.send(&mcast, b"deadbeef").await.unwrap();
+
+    let received = tokio::time::timeout(Duration::from_millis(200), rx1.recv()).await.is_ok();
+    assert_eq!(received, include == list_sender);
+}
+
+#[tokio::test]
+#[serial]
+async fn source_specific_multicast_needs_a_source() {
+    let mut udp = UdpManager::default();
+    let mcast = config(multicast_from("232.1.1.100", SourceFilter::Include(Default::default())),"0.0.0.0:6993");
+    let err = udp.subscribe(&mcast,None).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig { .. }));
+    assert_eq!(err.config(), &mcast.into());
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+#[serial]
+async fn datagrams_carry_destination_and_interface() {
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"0.0.0.0:6993");
+    let mcast = config(multicast_from("224.1.1.100", SourceFilter::Any),"0.0.0.0:6994");
+    let mcast_v6 = config_v6(multicast_v6("ff02::6993"),"[::]:6995");
+    let mut rx1 = udp.subscribe(&unicast,None).await.unwrap();
+    let mut rx2 = udp.subscribe(&mcast,None).await.unwrap();
+    let mut rx3 = udp.subscribe(&mcast_v6,None).await.unwrap();
+
+    udp.send(&unicast, b"deadbeef").await.unwrap();
+    udp.send(&mcast, b"deadbeef").await.unwrap();
+    udp.send(&mcast_v6, b"deadbeef").await.unwrap();
+
+    let r = rx1.recv().await.unwrap();
+    assert_eq!(r.destination, Some("127.0.0.1".parse().unwrap()));
+    assert_eq!(r.interface_name.as_deref(), Some("lo"));
+    assert_eq!(r.sender, "127.0.0.1:6993".parse().unwrap());
+
+    let r = rx2.recv().await.unwrap();
+    assert_eq!(r.destination, Some("224.1.1.100".parse().unwrap()));
+    assert!(r.interface_index.is_some());
+    assert!(r.interface_name.is_some());
+
+    let r = rx3.recv().await.unwrap();
+    assert_eq!(r.destination, Some("ff02::6993".parse().unwrap()));
+    assert!(r.interface_index.is_some());
+    assert!(r.interface_name.is_some());
+}
+
+use rudi::{options::ConnectionOptions, Timestamp};
+use std::time::SystemTime;
+
+#[rstest]
+#[case::kernel(true)]
+#[case::userspace(false)]
+#[tokio::test]
+#[serial]
+async fn datagrams_are_timestamped(#[case] timestamps: bool) {
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"127.0.0.1:6993");
+    let options = ConnectionOptions { timestamps, ..Default::default() };
+    let mut rx1 = udp.subscribe_with(&unicast,&options).await.unwrap();
+
+    let before = SystemTime::now();
+    udp.send(&unicast, b"deadbeef").await.unwrap();
+    let r = rx1.recv().await.unwrap();
+    let after = SystemTime::now();
+
+    assert_eq!(matches!(r.timestamp, Timestamp::Kernel(_)), timestamps);
+    assert!(r.timestamp.time() >= before && r.timestamp.time() <= after);
+}
+
+#[tokio::test]
+#[serial]
+async fn fan_out_shares_payload() {
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"127.0.0.1:6993");
+    let mut rx1 = udp.subscribe(&unicast,None).await.unwrap();
+    let mut rx2 = udp.subscribe(&unicast,None).await.unwrap();
+
+    udp.send(&unicast, b"deadbeef").await.unwrap();
+
+    let (r1, r2) = (rx1.recv().await.unwrap(), rx2.recv().await.unwrap());
+    //both subscribers point at the same receive buffer instead of their own copies
+    assert_eq!(r1.payload.as_ptr(), r2.payload.as_ptr());
+}
+
+#[rstest]
+#[case::single(None)]
+#[case::batched(Some(64))]
+#[tokio::test]
+#[serial]
+async fn batched_receive_keeps_datagrams_in_order(#[case] batch_size: Option<usize>) {
+    const BURST: u32 = 64;
+    const BURSTS: u32 = 200;
+    let mut udp = UdpManager::default();
+    let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+    let options = ConnectionOptions { batch_size, ..Default::default() };
+    let mut rx1 = udp.subscribe_with(&unicast,&options).await.unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+
+    //bursts stay well under the socket receive buffer so nothing is dropped by the kernel
+    for burst in 0..BURSTS {
+        for i in 0..BURST {
+            peer.send_to(&(burst * BURST + i).to_be_bytes(),"127.0.0.1:6993").await.unwrap();
+        }
+        for i in 0..BURST {
+            let r = rx1.recv().await.unwrap();
+            assert_eq!(r.payload, &(burst * BURST + i).to_be_bytes()[..]);
+            assert_eq!(r.sender, "127.0.0.1:6994".parse().unwrap());
+        }
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn oversized_batches_are_capped() {
+    let mut udp = UdpManager::default();
+    let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+    //uncapped this would be 100000 receive buffers of 64k
+    let options = ConnectionOptions { batch_size: Some(100_000), ..Default::default() };
+    let mut rx1 = udp.subscribe_with(&unicast,&options).await.unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    peer.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+    assert_eq!(rx1.recv().await.unwrap().payload, &b"deadbeef"[..]);
+}
+
+#[tokio::test]
+#[serial]
+async fn batched_datagrams_only_hold_their_own_size() {
+    let mut udp = UdpManager::default();
+    let any = config(CastMode::AnySource,"127.0.0.1:6993");
+    let options = ConnectionOptions { batch_size: Some(8), ..Default::default() };
+    let mut rx1 = udp.subscribe_with(&any,&options).await.unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    peer.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+    peer.send_to(b"feedface","127.0.0.1:6993").await.unwrap();
+
+    //the second one follows right after the first instead of a whole datagram sized slot later
+    let first = rx1.recv().await.unwrap().payload;
+    let second = rx1.recv().await.unwrap().payload;
+    assert_eq!(second, &b"feedface"[..]);
+    assert_eq!(unsafe { first.as_ptr().add(first.len()) }, second.as_ptr());
+}
+
+#[tokio::test]
+#[serial]
+async fn batched_datagrams_are_stamped_when_read() {
+    let mut udp = UdpManager::default();
+    let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+    let options = ConnectionOptions { batch_size: Some(8), distribution: Distribution::Lossless, channel_size: Some(1), ..Default::default() };
+    let mut rx1 = udp.subscribe_with(&unicast,&options).await.unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+
+    //the first fills the channel and the recv task holds on to the second, the rest wait in the socket
+    for i in 0u8..2 {
+        peer.send_to(&[i],"127.0.0.1:6993").await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    for i in 2u8..6 {
+        peer.send_to(&[i],"127.0.0.1:6993").await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    //a slow subscriber holds up handing out the rest of the batch, not when it was read
+    let mut stamps = vec![];
+    for i in 0u8..6 {
+        let r = rx1.recv().await.unwrap();
+        assert_eq!(r.payload, &[i][..]);
+        stamps.push(r.timestamp);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(matches!(stamps[2], Timestamp::Userspace(_)));
+    assert!(stamps[2..].iter().all(|stamp| *stamp == stamps[2]));
+}
+
+//a rough comparison rather than a check, run with
+//cargo test --release --test udpmanager receive_throughput -- --ignored --nocapture
+#[tokio::test]
+#[serial]
+#[ignore]
+async fn receive_throughput() {
+    const BURST: u32 = 64;
+    const BURSTS: u32 = 2000;
+    for batch_size in [None, Some(64)] {
+        let mut udp = UdpManager::default();
+        let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+        let options = ConnectionOptions { batch_size, ..Default::default() };
+        let mut rx1 = udp.subscribe_with(&unicast,&options).await.unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+
+        let start = std::time::Instant::now();
+        for burst in 0..BURSTS {
+            for i in 0..BURST {
+                peer.send_to(&(burst * BURST + i).to_be_bytes(),"127.0.0.1:6993").await.unwrap();
+            }
+            for _ in 0..BURST {
+                rx1.recv().await.unwrap();
+            }
+        }
+        let elapsed = start.elapsed();
+        println!("batch size {batch_size:?}: {:.0} datagrams/s", (BURST * BURSTS) as f64 / elapsed.as_secs_f64());
+        udp.shutdown().await;
+    }
+}
+
+#[rstest]
+#[case::gro(true)]
+#[case::no_gro(false)]
+#[tokio::test]
+#[serial]
+async fn gso_bursts_arrive_as_separate_datagrams(#[case] gro: bool) {
+    use std::os::fd::AsRawFd;
+
+    let mut udp = UdpManager::default();
+    let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+    let options = ConnectionOptions { gro, ..Default::default() };
+    let mut rx1 = udp.subscribe_with(&unicast,&options).await.unwrap();
+
+    //the sender hands the kernel one 8 byte segmented buffer, over loopback it stays coalesced
+    //up to the receiver, which either gets it as one GRO read or segmented by the kernel
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    let segment: libc::c_int = 8;
+    let ret = unsafe {
+        libc::setsockopt(
+            peer.as_raw_fd(),
+            libc::SOL_UDP,
+            libc::UDP_SEGMENT,
+            &segment as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&segment) as libc::socklen_t,
+        )
+    };
+    assert_eq!(ret, 0);
+    peer.send_to(b"deadbeef01234567feedface","127.0.0.1:6993").await.unwrap();
+
+    for expected in [&b"deadbeef"[..], b"01234567", b"feedface"] {
+        let r = tokio::time::timeout(Duration::from_secs(2), rx1.recv()).await.unwrap().unwrap();
+        assert_eq!(r.payload, expected);
+    }
+    assert_eq!(udp.stats().remove(&unicast.into()).unwrap().datagrams, 3);
+}
+
+use rudi::options::Distribution;
+use rudi::subscription::TryRecvError;
+
+fn options(distribution: Distribution, channel_size: usize) -> ConnectionOptions {
+    ConnectionOptions { distribution, channel_size: Some(channel_size), ..Default::default() }
+}
+
+#[tokio::test]
+#[serial]
+async fn lossless_subscribers_see_every_datagram() {
+    let mut udp = UdpManager::default();
+    let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+    let mut rx1 = udp.subscribe_with(&unicast,&options(Distribution::Lossless, 1)).await.unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+
+    for i in 0..8u8 {
+        peer.send_to(&[i],"127.0.0.1:6993").await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    //the rest wait in the socket until the subscriber makes room
+    assert!(udp.stats()[&unicast.clone().into()].datagrams < 8);
+
+    for i in 0..8u8 {
+        let r = tokio::time::timeout(Duration::from_secs(2), rx1.recv()).await.unwrap().unwrap();
+        assert_eq!(r.payload, &[i][..]);
+    }
+    assert_eq!(rx1.lagged(), 0);
+    assert_eq!(udp.stats()[&unicast.into()].lagged, 0);
+}
+
+#[tokio::test]
+#[serial]
+async fn stalled_lossless_subscriber_does_not_block_teardown() {
+    let mut udp = UdpManager::default();
+    let unicast = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+    let mut rx1 = udp.subscribe_with(&unicast,&options(Distribution::Lossless, 1)).await.unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    for _ in 0..8 {
+        peer.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert!(tokio::time::timeout(Duration::from_secs(2), udp.unsubscribe(&unicast)).await.unwrap());
+    //what was already in the channel is still handed out
+    assert!(rx1.recv().await.is_ok());
+    assert!(matches!(rx1.recv().await, Err(RecvError::Closed)));
+}
+
+#[tokio::test]
+#[serial]
+async fn latest_subscribers_only_see_the_newest_datagram() {
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"127.0.0.1:6993");
+    let mut rx1 = udp.subscribe_with(&unicast,&options(Distribution::Latest, 1)).await.unwrap();
+    assert!(matches!(rx1.try_recv(), Err(TryRecvError::Empty)));
+
+    let sock = udp.get_socket(&unicast).unwrap();
+    for payload in [b"deadbeef", b"feedface", b"01234567"] {
+        sock.send(payload).await.unwrap();
+    }
+    wait_for_stats(&udp, &unicast, 3).await;
+
+    assert_eq!(rx1.len(), 1);
+    assert_eq!(rx1.recv().await.unwrap().payload, &b"01234567"[..]);
+    assert!(matches!(rx1.try_recv(), Err(TryRecvError::Empty)));
+}
+
+use rudi::filter::Filter;
+
+#[tokio::test]
+#[serial]
+async fn filtered_subscribers_only_queue_matching_datagrams() {
+    let mut udp = UdpManager::default();
+    let broadcast = config(CastMode::Broadcast,"0.0.0.0:6993");
+    let all = udp.subscribe(&broadcast,None).await.unwrap();
+    let mut filtered = udp.subscribe_filtered(&broadcast, Filter::SenderPort(6994)).await.unwrap();
+    let mut prefixed = udp.subscribe_filtered(&broadcast, Filter::Prefix(b"dead"[..].into())).await.unwrap();
+
+    let wanted = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    let chatty = UdpSocket::bind("127.0.0.1:6995").await.unwrap();
+    chatty.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+    wanted.send_to(b"feedface","127.0.0.1:6993").await.unwrap();
+    chatty.send_to(b"01234567","127.0.0.1:6993").await.unwrap();
+    wait_for_stats(&udp, &broadcast, 3).await;
+
+    assert_eq!(all.len(), 3);
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered.recv().await.unwrap().payload, &b"feedface"[..]);
+    assert_eq!(prefixed.len(), 1);
+    assert_eq!(prefixed.recv().await.unwrap().payload, &b"deadbeef"[..]);
+    assert_eq!(udp.stats()[&broadcast.clone().into()].subscribers, 3);
+
+    //filtered subscribers keep the connection alive and are closed with it
+    drop(all);
+    assert_eq!(udp.count(), 1);
+    udp.unsubscribe(&broadcast).await;
+    assert!(matches!(filtered.recv().await, Err(RecvError::Closed)));
+}
+
+#[tokio::test]
+#[serial]
+async fn filtered_subscription_opens_with_options() {
+    let mut udp = UdpManager::default();
+    let any = config(CastMode::AnySource,"127.0.0.1:6993");
+    let options = ConnectionOptions { timestamps: true, batch_size: Some(8), ..Default::default() };
+    let mut filtered = udp.subscribe_filtered_with(&any, Filter::SenderPort(6994), &options).await.unwrap();
+
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    peer.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+    let r = filtered.recv().await.unwrap();
+    assert_eq!(r.payload, &b"deadbeef"[..]);
+    assert!(matches!(r.timestamp, Timestamp::Kernel(_)));
+}
+
+#[rstest]
+#[case::v4(config(CastMode::AnySource,"127.0.0.1:6993").into(), "127.0.0.1")]
+#[case::v6(config_v6(CastModeV6::AnySource,"[::1]:6993").into(), "::1")]
+#[tokio::test]
+#[serial]
+async fn any_source_unicast_reports_each_sender(#[case] ip_config: rudi::IpConfig, #[case] host: &str) {
+    let mut udp = UdpManager::default();
+    let mut rx1 = udp.subscribe(&ip_config,None).await.unwrap();
+    let listener = ip_config.bind_addr();
+
+    let first = UdpSocket::bind((host, 6994)).await.unwrap();
+    let second = UdpSocket::bind((host, 6995)).await.unwrap();
+    first.send_to(b"deadbeef", listener).await.unwrap();
+    let r = rx1.recv().await.unwrap();
+    assert_eq!(r.sender, first.local_addr().unwrap());
+    second.send_to(b"feedface", listener).await.unwrap();
+    let r = rx1.recv().await.unwrap();
+    assert_eq!(r.sender, second.local_addr().unwrap());
+
+    //there is no peer to send to by default, replies go to an observed sender
+    let publisher = udp.publisher(&ip_config).unwrap();
+    assert_eq!(publisher.send(b"deadbeef").await.unwrap_err().kind(), std::io::ErrorKind::NotConnected);
+    publisher.send_to(b"deadbeef", r.sender).await.unwrap();
+    let mut buf = [0; 8];
+    let (_, from) = second.recv_from(&mut buf).await.unwrap();
+    assert_eq!(from, listener);
+}
+
+#[tokio::test]
+#[serial]
+async fn unicast_peers_share_a_socket() {
+    let mut udp = UdpManager::default();
+    let peer = |port: u16| config(CastMode::Unicast(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)),"127.0.0.1:6993");
+    let mut rx1 = udp.subscribe(peer(6994),None).await.unwrap();
+    let mut rx2 = udp.subscribe(peer(6995),None).await.unwrap();
+    let any = config(CastMode::AnySource,"127.0.0.1:6993");
+    let mut rx_any = udp.subscribe(&any,None).await.unwrap();
+
+    assert_eq!(udp.count(), 3);
+    let socket = udp.get_socket(peer(6994)).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&socket, &udp.get_socket(peer(6995)).unwrap()));
+    assert!(std::sync::Arc::ptr_eq(&socket, &udp.get_socket(&any).unwrap()));
+
+    let plc1 = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    let plc2 = UdpSocket::bind("127.0.0.1:6995").await.unwrap();
+    let unknown = UdpSocket::bind("127.0.0.1:6996").await.unwrap();
+    unknown.send_to(b"01234567","127.0.0.1:6993").await.unwrap();
+    plc2.send_to(b"feedface","127.0.0.1:6993").await.unwrap();
+    plc1.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+
+    //every datagram goes to the config of its sender, the catch-all only gets unknown senders
+    assert_eq!(rx1.recv().await.unwrap().payload, &b"deadbeef"[..]);
+    assert_eq!(rx2.recv().await.unwrap().payload, &b"feedface"[..]);
+    assert_eq!(rx_any.recv().await.unwrap().payload, &b"01234567"[..]);
+    assert!(rx1.is_empty() && rx2.is_empty() && rx_any.is_empty());
+    assert_eq!(udp.stats()[&peer(6994).into()].datagrams, 1);
+
+    //each peer is sent to even though the socket isn't connected to any of them
+    udp.send(peer(6995), b"deadbeef").await.unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(plc2.recv_from(&mut buf).await.unwrap(), (8, "127.0.0.1:6993".parse().unwrap()));
+
+    //the others keep going when a peer leaves, the port is free once they all have
+    assert!(udp.unsubscribe(peer(6994)).await);
+    assert!(matches!(rx1.recv().await, Err(RecvError::Closed)));
+    plc2.send_to(b"feedface","127.0.0.1:6993").await.unwrap();
+    assert_eq!(rx2.recv().await.unwrap().payload, &b"feedface"[..]);
+    plc1.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+    assert_eq!(rx_any.recv().await.unwrap().sender, plc1.local_addr().unwrap());
+
+    drop(socket);
+    udp.unsubscribe(peer(6995)).await;
+    udp.unsubscribe(&any).await;
+    assert!(UdpSocket::bind("127.0.0.1:6993").await.is_ok());
+}
+
+#[tokio::test]
+#[serial]
+async fn last_unicast_peer_is_reconnected() {
+    let mut udp = UdpManager::default();
+    let mut events = udp.events();
+    let peer = |port: u16| config(CastMode::Unicast(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)),"127.0.0.1:6993");
+    let _rx1 = udp.subscribe(peer(6994),None).await.unwrap();
+    let _rx2 = udp.subscribe(peer(6995),None).await.unwrap();
+    assert!(udp.unsubscribe(peer(6995)).await);
+
+    //only a connected socket hears about the icmp port unreachable from the dead peer
+    udp.send(peer(6994), b"deadbeef").await.unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(1), events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, ConnectionEvent::RecvError { config: peer(6994).into(), kind: std::io::ErrorKind::ConnectionRefused });
+}
+
+//a multicast capable interface other than loopback with an ipv4 address, whatever the host calls it
+fn other_interface() -> Option<(String, Ipv4Addr)> {
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } == -1 {
+        return None;
+    }
+    let (mut found, mut cur) = (None, addrs);
+    while !cur.is_null() && found.is_none() {
+        let ifa = unsafe { &*cur };
+        let flags = ifa.ifa_flags as libc::c_int;
+        let usable = flags & libc::IFF_UP != 0 && flags & libc::IFF_MULTICAST != 0 && flags & libc::IFF_LOOPBACK == 0;
+        if usable && !ifa.ifa_addr.is_null() && unsafe { (*ifa.ifa_addr).sa_family } as libc::c_int == libc::AF_INET {
+            let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+            let name = unsafe { std::ffi::CStr::from_ptr(ifa.ifa_name) }.to_string_lossy().into_owned();
+            found = Some((name, Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))));
+        }
+        cur = ifa.ifa_next;
+    }
+    unsafe { libc::freeifaddrs(addrs) };
+    found
+}
+
+#[rstest]
+#[case::loopback(true)]
+#[case::other_device(false)]
+#[tokio::test]
+#[serial]
+async fn bound_device_restricts_receive(#[case] loopback: bool) {
+    let device = if loopback {
+        "lo".to_string()
+    } else {
+        let Some((name, _)) = other_interface() else {
+            eprintln!("skipped, no interface besides loopback");
+            return;
+        };
+        name
+    };
+    let mut udp = UdpManager::default();
+    let broadcast = IpConfigV4 {
+        cast_mode: CastMode::Broadcast,
+        bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: Some(device),
+        sharing: None,
+    };
+    let mut rx1 = udp.subscribe(&broadcast,None).await.unwrap();
+
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    peer.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+
+    let r = tokio::time::timeout(Duration::from_millis(200), rx1.recv()).await;
+    assert_eq!(r.is_ok(), loopback);
+}
+
+#[tokio::test]
+#[serial]
+async fn unknown_device_fails_to_subscribe() {
+    let mut udp = UdpManager::default();
+    let broadcast = IpConfigV4 {
+        cast_mode: CastMode::Broadcast,
+        bind_addr: "0.0.0.0:6993".parse::<SocketAddrV4>().unwrap(),
+        device: Some("nosuchdev0".to_string()),
+        sharing: None,
+    };
+    let err = udp.subscribe(&broadcast,None).await.unwrap_err();
+    assert!(matches!(err, Error::Interface { .. }));
+    assert_eq!(udp.count(), 0);
+}
+
+#[rstest]
+#[case::any_source(false)]
+#[case::source_specific(true)]
+#[tokio::test]
+#[serial]
+async fn multicast_interface_by_name(#[case] source_specific: bool) {
+    let Some((name, addr)) = other_interface() else {
+        eprintln!("skipped, no interface besides loopback");
+        return;
+    };
+    //sent out of the same interface, so its address is the source
+    let sources = if source_specific { SourceFilter::Include([addr].into()) } else { SourceFilter::Any };
+    let mut udp = UdpManager::default();
+    let mcast = config(CastMode::Multicast(MulticastConfig{
+        group: "232.1.1.100".parse::<Ipv4Addr>().unwrap(),
+        interface: Interface::Name(name),
+        sources,
+    }),"0.0.0.0:6993");
+    let socket = SocketOptions { multicast_interface: Some(addr), ..Default::default() };
+    let mut rx1 = udp.subscribe_with(&mcast,&ConnectionOptions { socket, ..Default::default() }).await.unwrap();
+
+    udp.send(&mcast, b"deadbeef").await.unwrap();
+    let r = tokio::time::timeout(Duration::from_secs(1), rx1.recv()).await.unwrap().unwrap();
+    assert_eq!(r.payload, &b"deadbeef"[..]);
+
+    //leaving goes by the interface the group was joined on
+    let mut events = udp.events();
+    assert!(udp.unsubscribe(&mcast).await);
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn unknown_multicast_interface_fails_to_subscribe() {
+    let mut udp = UdpManager::default();
+    let mcast = config(CastMode::Multicast(MulticastConfig{
+        group: "225.1.1.100".parse::<Ipv4Addr>().unwrap(),
+        interface: Interface::Name("nosuchdev0".to_string()),
+        sources: SourceFilter::Any,
+    }),"0.0.0.0:6993");
+    let err = udp.subscribe(&mcast,None).await.unwrap_err();
+    assert!(matches!(err, Error::Interface { .. }));
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    assert!(err.to_string().contains("nosuchdev0"));
+}
+
+use rudi::conflict::{Conflict, ConflictReason};
+
+#[rstest]
+#[case::wildcard_and_specific(config(CastMode::AnySource,"0.0.0.0:6993"), config(unicast(),"127.0.0.1:6993"))]
+#[case::unicast_and_multicast(config(unicast(),"0.0.0.0:6993"), config(multicast(),"0.0.0.0:6993"))]
+#[tokio::test]
+#[serial]
+async fn overlapping_unicast_is_refused(#[case] first: IpConfigV4, #[case] second: IpConfigV4) {
+    let mut udp = UdpManager::default();
+    let _rx1 = udp.subscribe(&first,None).await.unwrap();
+
+    let err = udp.subscribe(&second,None).await.unwrap_err();
+    assert!(matches!(err, Error::AddrInUse { .. }));
+    assert_eq!(err.conflict().unwrap(), &Conflict { config: second.into(), existing: first.into(), reason: ConflictReason::Unicast });
+    assert_eq!(udp.count(), 1);
+}
+
+#[rstest]
+#[case::unicast(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()))]
+#[case::any_source(CastMode::AnySource)]
+#[tokio::test]
+#[serial]
+async fn same_peer_with_other_sharing_is_refused(#[case] cast_mode: CastMode) {
+    let mut udp = UdpManager::default();
+    let first = config(cast_mode,"127.0.0.1:6993");
+    let second = IpConfigV4 { sharing: Some(SocketSharing::Exclusive), ..first.clone() };
+    let mut rx1 = udp.subscribe(&first,None).await.unwrap();
+
+    let err = udp.subscribe(&second,None).await.unwrap_err();
+    assert_eq!(err.conflict().unwrap(), &Conflict { config: second.into(), existing: first.clone().into(), reason: ConflictReason::SamePeer });
+
+    //the first one is left as it was
+    assert_eq!(udp.count(), 1);
+    assert_eq!(udp.stats()[&first.clone().into()].subscribers, 1);
+    let peer = UdpSocket::bind("127.0.0.1:6994").await.unwrap();
+    peer.send_to(b"deadbeef","127.0.0.1:6993").await.unwrap();
+    assert_eq!(rx1.recv().await.unwrap().payload, &b"deadbeef"[..]);
+}
+
+#[rstest]
+#[case::other_sharing(Some(SocketSharing::ReusePort), ConnectionOptions::default(), Some(ConflictReason::Sharing))]
+#[case::other_socket_options(None, ConnectionOptions { batch_size: Some(8), ..Default::default() }, Some(ConflictReason::Options))]
+#[case::same_sharing(Some(SocketSharing::Exclusive), ConnectionOptions::default(), None)]
+#[case::own_channel(None, ConnectionOptions { channel_size: Some(4), distribution: Distribution::Lossless, ..Default::default() }, None)]
+#[tokio::test]
+#[serial]
+async fn shared_socket_keeps_its_setup(#[case] sharing: Option<SocketSharing>, #[case] options: ConnectionOptions, #[case] refused: Option<ConflictReason>) {
+    let mut udp = UdpManager::default();
+    let first = config(CastMode::Unicast("127.0.0.1:6994".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993");
+    let second = IpConfigV4 { sharing, ..config(CastMode::Unicast("127.0.0.1:6995".parse::<SocketAddrV4>().unwrap()),"127.0.0.1:6993") };
+    let _rx1 = udp.subscribe(&first,None).await.unwrap();
+
+    let result = udp.subscribe_with(&second,&options).await;
+    match refused {
+        Some(reason) => {
+            let err = result.unwrap_err();
+            assert_eq!(err.conflict().unwrap(), &Conflict { config: second.into(), existing: first.into(), reason });
+            assert_eq!(udp.count(), 1);
+        }
+        None => {
+            let _rx2 = result.unwrap();
+            assert_eq!(udp.count(), 2);
+        }
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn address_taken_elsewhere_is_not_a_conflict() {
+    let _sock = UdpSocket::bind("0.0.0.0:6993").await.unwrap();
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"0.0.0.0:6993");
+
+    let err = udp.subscribe(&unicast,None).await.unwrap_err();
+    assert!(matches!(err, Error::AddrInUse { .. }));
+    assert!(err.conflict().is_none());
+    assert_eq!(std::io::Error::from(err).kind(), std::io::ErrorKind::AddrInUse);
+}
+
+#[rstest]
+#[case::v4(config(multicast_from("225.1.1.100", SourceFilter::Any),"0.0.0.0:6993").into(), config(multicast_from("225.1.1.101", SourceFilter::Any),"0.0.0.0:6993").into())]
+#[case::v6(config_v6(multicast_v6("ff02::1:6993"),"[::]:6993").into(), config_v6(multicast_v6("ff02::2:6993"),"[::]:6993").into())]
+#[tokio::test]
+#[serial]
+async fn overlapping_multicast_groups_stay_apart(#[case] mcast1: rudi::IpConfig, #[case] mcast2: rudi::IpConfig) {
+    let mut udp = UdpManager::default();
+    let mut rx1 = udp.subscribe(&mcast1,None).await.unwrap();
+    let mut rx2 = udp.subscribe(&mcast2,None).await.unwrap();
+
+    udp.send(&mcast2, b"feedface").await.unwrap();
+    udp.send(&mcast1, b"deadbeef").await.unwrap();
+
+    assert_eq!(rx1.recv().await.unwrap().payload, &b"deadbeef"[..]);
+    assert_eq!(rx2.recv().await.unwrap().payload, &b"feedface"[..]);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(rx1.is_empty() && rx2.is_empty());
+}
+
+use rudi::options::SocketOptions;
+
+#[tokio::test]
+#[serial]
+async fn socket_options_are_applied() {
+    let mut udp = UdpManager::default();
+    let mcast = config(multicast(),"0.0.0.0:6993");
+    let socket = SocketOptions {
+        recv_buffer: Some(128 << 10),
+        send_buffer: Some(128 << 10),
+        tos: Some(46 << 2),
+        ttl: Some(7),
+        multicast_ttl: Some(3),
+        multicast_loop: Some(false),
+        multicast_interface: Some(Ipv4Addr::LOCALHOST),
+        priority: Some(4),
+        ..Default::default()
+    };
+    let mut rx1 = udp.subscribe_with(&mcast,&ConnectionOptions { socket, ..Default::default() }).await.unwrap();
+
+    let applied = udp.socket_options(&mcast).unwrap();
+    assert!(applied.recv_buffer.unwrap() >= 128 << 10);
+    assert!(applied.send_buffer.unwrap() >= 128 << 10);
+    assert_eq!(applied.tos, Some(46 << 2));
+    assert_eq!(applied.ttl, Some(7));
+    assert_eq!(applied.multicast_ttl, Some(3));
+    assert_eq!(applied.multicast_interface, Some(Ipv4Addr::LOCALHOST));
+    assert_eq!(applied.priority, Some(4));
+
+    //with loop off our own datagrams don't come back
+    assert_eq!(applied.multicast_loop, Some(false));
+    udp.send(&mcast, b"deadbeef").await.unwrap();
+    assert!(tokio::time::timeout(Duration::from_millis(200), rx1.recv()).await.is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn privileged_socket_options_are_applied() {
+    //forcing the buffer past rmem_max and setting a mark need CAP_NET_ADMIN
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping, needs root");
+        return;
+    }
+    let mut udp = UdpManager::default();
+    let unicast = config(unicast(),"127.0.0.1:6993");
+    let socket = SocketOptions { recv_buffer: Some(16 << 20), force_recv_buffer: true, mark: Some(42), ..Default::default() };
+    let _rx1 = udp.subscribe_with(&unicast,&ConnectionOptions { socket, ..Default::default() }).await.unwrap();
+
+    let applied = udp.socket_options(&unicast).unwrap();
+    assert!(applied.recv_buffer.unwrap() >= 16 << 20);
+    assert_eq!(applied.mark, Some(42));
+}
+
+#[tokio::test]
+#[serial]
+async fn multicast_interface_is_v4_only() {
+    let mut udp = UdpManager::default();
+    let unicast = config_v6(CastModeV6::AnySource,"[::]:6993");
+    let socket = SocketOptions { multicast_interface: Some(Ipv4Addr::LOCALHOST), ..Default::default() };
+    let err = udp.subscribe_with(&unicast,&ConnectionOptions { socket, ..Default::default() }).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig { .. }));
+    assert!(matches!(udp.socket_options(&unicast), Err(Error::NotSubscribed { .. })));
+}